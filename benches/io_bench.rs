@@ -1,5 +1,5 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use ringest_io::{Registry, IoTarget};
+use criterion::{criterion_group, criterion_main, Criterion};
+use ringest_io::{IoConfig, MemoryTarget, Registry, IoTarget};
 use std::{sync::Arc, time::Duration};
 use bytes::Bytes;
 
@@ -18,9 +18,9 @@ fn open_file_windows(path: &str) -> std::fs::File {
     options.open(path).expect("Failed to open file with share mode")
 }
 
-async fn bench_read_performance(reg: Arc<Registry>, id: u64) {
-    let writer = reg.get_writer::<std::fs::File>(id).unwrap();
-    let reader = reg.get_reader::<std::fs::File>(id).unwrap();
+async fn bench_read_performance<T: IoTarget>(reg: Arc<Registry>, id: u64) {
+    let writer = reg.get_writer::<T>(id).unwrap();
+    let reader = reg.get_reader::<T>(id).unwrap();
     let data = Bytes::from(vec![1u8; 4096]);
     
     for i in 0..100 {
@@ -42,13 +42,13 @@ fn read_benchmark(c: &mut Criterion) {
     let registry = rt.block_on(async {
         let reg = Arc::new(Registry::new());
         let file = open_file_windows(&path);
-        reg.insert(1, file, Duration::from_millis(5000), Duration::from_millis(5000));
+        reg.insert(1, file, Duration::from_millis(5000), Duration::from_millis(5000)).unwrap();
         reg
     });
 
     c.bench_function("read_after_write_hot", |b| {
         b.to_async(&rt).iter(|| {
-            bench_read_performance(registry.clone(), 1)
+            bench_read_performance::<std::fs::File>(registry.clone(), 1)
         });
     });
 
@@ -56,5 +56,73 @@ fn read_benchmark(c: &mut Criterion) {
     let _ = std::fs::remove_file(&path);
 }
 
-criterion_group!(benches, read_benchmark);
+fn memory_benchmark(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let registry = rt.block_on(async {
+        let reg = Arc::new(Registry::new());
+        reg.insert(1, MemoryTarget::new(), Duration::from_millis(5000), Duration::from_millis(5000)).unwrap();
+        reg
+    });
+
+    c.bench_function("read_after_write_hot_memory", |b| {
+        b.to_async(&rt).iter(|| {
+            bench_read_performance::<MemoryTarget>(registry.clone(), 1)
+        });
+    });
+}
+
+async fn bench_flush_and_read<T: IoTarget>(reg: Arc<Registry>, id: u64) {
+    let writer = reg.get_writer::<T>(id).unwrap();
+    let reader = reg.get_reader::<T>(id).unwrap();
+    let small = Bytes::from(vec![2u8; 512]);
+
+    // Spaced out so the flush hands the target 64 separate runs.
+    for i in 0..64 {
+        writer.write_at(i * 1024, small.clone()).await.unwrap();
+    }
+    writer.flush().await.unwrap();
+
+    for i in 0..64 {
+        let _ = reader.read_at(i * 1024, 512).await.unwrap();
+    }
+}
+
+fn target_comparison(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
+    let file_path = format!("compare_bench_file_{}.dat", timestamp);
+    let registry = Arc::new(Registry::new());
+    registry.insert_with(1, open_file_windows(&file_path), IoConfig::default()).unwrap();
+
+    let mut group = c.benchmark_group("flush_and_read");
+    group.bench_function("std_file", |b| {
+        b.to_async(&rt).iter(|| bench_flush_and_read::<std::fs::File>(registry.clone(), 1));
+    });
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    let uring_path = {
+        let path = format!("compare_bench_uring_{}.dat", timestamp);
+        registry.insert_with(2, ringest_io::UringTarget::open(&path).unwrap(), IoConfig::default()).unwrap();
+        group.bench_function("uring", |b| {
+            b.to_async(&rt).iter(|| bench_flush_and_read::<ringest_io::UringTarget>(registry.clone(), 2));
+        });
+        path
+    };
+    group.finish();
+
+    drop(registry);
+    let _ = std::fs::remove_file(&file_path);
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    let _ = std::fs::remove_file(&uring_path);
+}
+
+criterion_group!(benches, read_benchmark, memory_benchmark, target_comparison);
 criterion_main!(benches);
\ No newline at end of file
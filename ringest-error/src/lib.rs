@@ -10,6 +10,18 @@ pub enum Error {
     #[error("Operation timed out")]
     Timeout,
 
+    #[error("Write queue is over its memory budget")]
+    QueueFull,
+
+    #[error("Snapshot went over its memory limit and dropped its data")]
+    SnapshotExpired,
+
+    #[error("Target is closed and takes no more writes")]
+    Closed,
+
+    #[error("Target error: {0}")]
+    Target(#[from] TargetError),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -41,10 +53,42 @@ pub enum FileSystemError {
     PermissionDenied,
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum TargetError {
+    #[error("No target registered under id {0}")]
+    NotFound(u64),
+
+    #[error("Target {id} is `{actual}`, not `{expected}`")]
+    WrongType {
+        id: u64,
+        expected: &'static str,
+        actual: &'static str,
+    },
+
+    #[error("A target is already registered under id {0}")]
+    AlreadyRegistered(u64),
+
+    #[error("Handle to target {0} is stale: the id was registered again since")]
+    Stale(u64),
+
+    #[error("Target {0} has a durability policy but no WAL, so a crash could tear a transaction")]
+    TransactionNeedsWal(u64),
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 impl From<tokio::time::error::Elapsed> for Error {
     fn from(_value: tokio::time::error::Elapsed) -> Self {
         Error::Timeout
     }
-}
\ No newline at end of file
+}
+
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io(e) => e,
+            Error::Timeout => std::io::Error::new(std::io::ErrorKind::TimedOut, value),
+            other => std::io::Error::other(other),
+        }
+    }
+}
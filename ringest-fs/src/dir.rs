@@ -1,10 +1,10 @@
 use futures::{FutureExt, future::BoxFuture};
-use tokio::{fs::{self, DirEntry}, task::JoinSet};
+use tokio::task::JoinSet;
 use std::{sync::Arc, path::PathBuf};
 
 use dashmap::DashMap;
 use ringest_error::{Error, FileSystemError, Result};
-use crate::{IO_REGISTRY, REGISTERED_FILES, file::File, filter::{FileType, Filter}};
+use crate::{IO_REGISTRY, file::File, filter::{FileType, Filter}};
 
 pub struct DirStats {
     pub total_size: u64,
@@ -63,17 +63,13 @@ impl Directory {
     }
 
     pub async fn remove(&self, name: &str) -> Result<()> {
-        if let Some((_, file_arc)) = self.subfiles.remove(name) {
+        let file = self.subfiles.get(name).map(|entry| Arc::clone(entry.value()));
+        if let Some(file_arc) = file {
+            // Removal flushes the queued writes, so it runs before the unlink. If they cannot be
+            // written out, the file stays both listed and on disk and the error is returned.
+            IO_REGISTRY.remove(file_arc.handle.id()).await?;
+            self.subfiles.remove(name);
             tokio::fs::remove_file(&file_arc.path).await?;
-            let id = {
-                let entry = REGISTERED_FILES
-                    .iter()
-                    .find(|f| f.0 == name && f.1 == self.path)
-                    .ok_or_else(|| Error::FileSystemError(FileSystemError::PathNotFound(PathBuf::from(name))))?;
-
-                *entry.key() 
-            };
-            let _ = IO_REGISTRY.remove(id);
 
             return Ok(())
         }
@@ -162,14 +158,28 @@ impl Directory {
         res_f: Arc<DashMap<String, Arc<File>>>, 
         depth: u64
     ) -> BoxFuture<'static, ()> {
-        async move {
-            if let Some(max_depth) = filter.recursive_depth {
-                if depth > max_depth { return; }
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "scan", path = %path.display(), depth,
+            dirs = tracing::field::Empty, files = tracing::field::Empty,
+            outcome = tracing::field::Empty, error = tracing::field::Empty,
+        );
+
+        let scan = async move {
+            if let Some(max_depth) = filter.recursive_depth
+                && depth > max_depth {
+                #[cfg(feature = "tracing")]
+                tracing::Span::current().record("outcome", "too deep");
+                return;
             }
 
             let mut entries = match tokio::fs::read_dir(&path).await {
                 Ok(e) => e,
-                Err(_) => return,
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::Span::current().record("outcome", "unreadable").record("error", tracing::field::display(&_e));
+                    return;
+                }
             };
 
             let mut set = JoinSet::new();
@@ -177,9 +187,8 @@ impl Directory {
             while let Ok(Some(entry)) = entries.next_entry().await {
                 let file_name = entry.file_name().to_string_lossy().to_string();
 
-                if let Some(excludes) = &filter.exclude_dirs {
-                    if excludes.contains(&file_name) { continue; }
-                }
+                if let Some(excludes) = &filter.exclude_dirs
+                    && excludes.contains(&file_name) { continue; }
 
                 let metadata = match entry.metadata().await {
                     Ok(m) => m,
@@ -216,7 +225,17 @@ impl Directory {
                 }
             }
 
-            while let Some(_) = set.join_next().await {}
-        }.boxed()
+            #[cfg(feature = "tracing")]
+            tracing::Span::current()
+                .record("dirs", res_d.len())
+                .record("files", res_f.len())
+                .record("outcome", "ok");
+
+            while set.join_next().await.is_some() {}
+        };
+
+        #[cfg(feature = "tracing")]
+        let scan = tracing::Instrument::instrument(scan, span);
+        scan.boxed()
     }
 }
\ No newline at end of file
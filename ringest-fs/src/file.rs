@@ -1,20 +1,102 @@
+use async_trait::async_trait;
 use bytes::Bytes;
-use ringest_io::{BufferReader, BufferWriter};
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter, SeekFrom};
-use std::{fs::Metadata, hash::{DefaultHasher, Hash, Hasher}, io::Write, sync::Arc, time::{Duration, SystemTime}};
+use ringest_io::{BufferReader, BufferWriter, IoTarget, MmapTarget, SyncMode, TargetHandle};
+use std::{fs::Metadata, io::Write, sync::atomic::{AtomicU64, Ordering}, time::{Duration, SystemTime}};
 use crate::IO_REGISTRY;
 use ringest_error::{Error, FileSystemError, Result};
 
-#[cfg(unix)]
-use std::os::unix::fs::FileExt;
-
-#[cfg(windows)]
-use std::os::windows::fs::FileExt;
-
 #[cfg(feature = "regex")]
 use regex::Regex;
 use tokio::fs::DirEntry;
 
+/// How a [`File`] reaches the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Positional reads and writes on the file
+    #[default]
+    Std,
+    /// A memory mapping of the file, suited to read-mostly data
+    Mmap,
+    /// A read-only memory mapping; reads return slices of the mapping without a copy,
+    /// and writing the file fails
+    MmapReadOnly,
+}
+
+/// Target behind a [`File`], whichever backend it was opened with.
+pub(crate) enum Target {
+    Std(std::fs::File),
+    Mmap(MmapTarget),
+}
+
+impl Target {
+    fn new(file: std::fs::File, backend: Backend) -> Result<Self> {
+        Ok(match backend {
+            Backend::Std => Self::Std(file),
+            Backend::Mmap => Self::Mmap(MmapTarget::new(file)?),
+            Backend::MmapReadOnly => Self::Mmap(MmapTarget::read_only(file)?),
+        })
+    }
+}
+
+#[async_trait]
+impl IoTarget for Target {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        match self {
+            Self::Std(file) => file.read_at(offset, len).await,
+            Self::Mmap(map) => map.read_at(offset, len).await,
+        }
+    }
+
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        match self {
+            Self::Std(file) => file.write_at(content, offset).await,
+            Self::Mmap(map) => map.write_at(content, offset).await,
+        }
+    }
+
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        match self {
+            Self::Std(file) => file.write_vectored_at(writes).await,
+            Self::Mmap(map) => map.write_vectored_at(writes).await,
+        }
+    }
+
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        match self {
+            Self::Std(file) => file.read_vectored_at(ranges).await,
+            Self::Mmap(map) => map.read_vectored_at(ranges).await,
+        }
+    }
+
+    async fn write_batch_at(&self, batch: &[Vec<(u64, Bytes)>]) -> Vec<Result<()>> {
+        match self {
+            Self::Std(file) => file.write_batch_at(batch).await,
+            Self::Mmap(map) => map.write_batch_at(batch).await,
+        }
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        match self {
+            Self::Std(file) => file.sync(mode).await,
+            Self::Mmap(map) => map.sync(mode).await,
+        }
+    }
+
+    async fn len(&self) -> Result<u64> {
+        match self {
+            Self::Std(file) => IoTarget::len(file).await,
+            Self::Mmap(map) => map.len().await,
+        }
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        match self {
+            Self::Std(file) => IoTarget::set_len(file, len).await,
+            Self::Mmap(map) => map.set_len(len).await,
+        }
+    }
+}
+
 pub struct File {
     pub name: String,
     pub path: String,
@@ -22,19 +104,17 @@ pub struct File {
     pub created_at: SystemTime,
     pub accessed_at: SystemTime,
     pub extension: String,
-    pub(crate) writer: BufferWriter<std::fs::File>,
-    pub(crate) reader: BufferReader<std::fs::File>,
+    pub(crate) handle: TargetHandle<Target>,
+    pub(crate) writer: BufferWriter<Target>,
+    pub(crate) reader: BufferReader<Target>,
     pub(crate) metadata: Metadata,
+    /// Length as of the last write through this file, queued writes included
+    pub(crate) size: AtomicU64,
 }
 
 impl File {
     pub fn new(path: &str, content: String) -> Result<Self> {
-        let extension = extension(&path.to_string()).unwrap_or("UNKNOWN".to_string());        
-        let abs_path = std::path::Path::new(path).canonicalize().unwrap_or(path.into());
-
-        let mut hasher = DefaultHasher::new();
-        abs_path.hash(&mut hasher);
-        let file_id = hasher.finish();
+        let extension = extension(path).unwrap_or("UNKNOWN".to_string());        
 
         let mut file = std::fs::File::options()
             .create(true)
@@ -47,48 +127,41 @@ impl File {
 
         let metadata = file.metadata()?;
         
-        IO_REGISTRY.insert(file_id, file, Duration::from_millis(1000), Duration::from_millis(1000));
-
-        let writer = IO_REGISTRY.get_writer::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get writer".to_string()))?;
-        let reader = IO_REGISTRY.get_reader::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get reader".to_string()))?;
+        let (handle, writer, reader) = register(Target::Std(file))?;
 
         Ok(Self {
-            name: name(&path.to_string())?,
+            name: name(path)?,
             extension,
             path: path.to_string(),
             last_edit: SystemTime::now(),
             created_at: SystemTime::now(),
             accessed_at: SystemTime::now(),
+            handle,
             writer,
             reader,
+            size: AtomicU64::new(metadata.len()),
             metadata,
         })
     }
 
     pub fn open(path: &str) -> Result<Self> {
+        Self::open_with(path, Backend::default())
+    }
+
+    /// Opens `path` through the given backend.
+    pub fn open_with(path: &str, backend: Backend) -> Result<Self> {
         let file = std::fs::File::options()
             .read(true)
-            .write(true)
+            .write(backend != Backend::MmapReadOnly)
             .open(path)?;
         let meta = file.metadata()?;
-        let name = name(&path.to_string()).unwrap_or("UNKNOWN".to_string());
-        let extension = extension(&path.to_string()).unwrap_or("UNKNOWN".to_string());
+        let name = name(path).unwrap_or("UNKNOWN".to_string());
+        let extension = extension(path).unwrap_or("UNKNOWN".to_string());
         let last_edit = meta.modified()?;
         let accessed_at = meta.accessed()?;
         let created_at = meta.created()?;
 
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        let file_id = hasher.finish();
-
-        IO_REGISTRY.insert(file_id, file, Duration::from_millis(1000), Duration::from_millis(1000));
-
-        let writer = IO_REGISTRY.get_writer::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get writer".to_string()))?;
-        let reader = IO_REGISTRY.get_reader::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get reader".to_string()))?;
+        let (handle, writer, reader) = register(Target::new(file, backend)?)?;
 
         Ok(Self {
             name,
@@ -97,8 +170,10 @@ impl File {
             last_edit,
             created_at,
             accessed_at,
+            handle,
             reader,
             writer,
+            size: AtomicU64::new(meta.len()),
             metadata: meta,
         })
     }
@@ -111,16 +186,7 @@ impl File {
             .write(true)
             .open(&path)?;
 
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        let file_id = hasher.finish();
-
-        IO_REGISTRY.insert(file_id, file, Duration::from_millis(1000), Duration::from_millis(1000));
-
-        let writer = IO_REGISTRY.get_writer::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get writer".to_string()))?;
-        let reader = IO_REGISTRY.get_reader::<std::fs::File>(file_id)
-            .ok_or(Error::Internal("Failed to get reader".to_string()))?;
+        let (handle, writer, reader) = register(Target::Std(file))?;
 
         Ok(Self {
             name: entry.file_name().to_string_lossy().to_string(),
@@ -129,8 +195,10 @@ impl File {
             created_at: meta.created().unwrap_or(SystemTime::now()),
             accessed_at: meta.accessed().unwrap_or(SystemTime::now()),
             extension: ext,
+            handle,
             reader,
             writer,
+            size: AtomicU64::new(meta.len()),
             metadata: meta,
         })
     }
@@ -158,23 +226,34 @@ impl File {
         self.metadata.permissions().readonly()
     }
 
+    /// Replaces the whole content, shrinking the file if it was longer.
     pub async fn rewrite(&self, content: String) -> Result<()> {
+        let len = content.len() as u64;
         self.writer.write_at(0, Bytes::from(content)).await?;
-        Ok(())
+        self.writer.set_len(len).await?;
+        self.refresh_size().await
     }
 
     pub async fn write_at(&self, offset: u64, content: String) -> Result<()> {
         self.writer.write_at(offset, Bytes::from(content)).await?;
-        Ok(())
+        self.refresh_size().await
     }
 
     pub async fn append(&self, content: String) -> Result<()> {
-        self.writer.write_at(self.size(), Bytes::from(content)).await?;
-        Ok(())
+        let end = self.writer.len().await?;
+        self.writer.write_at(end, Bytes::from(content)).await?;
+        self.refresh_size().await
+    }
+
+    /// Reads `len` bytes at `offset`. Through [`Backend::MmapReadOnly`] they are not copied.
+    pub async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        self.reader.read_at(offset, len).await
     }
 
     pub async fn content(&self) -> Result<String> {
-        let bytes = self.reader.read_at(0, self.size()).await?;
+        let len = self.reader.len().await?;
+        self.size.store(len, Ordering::Relaxed);
+        let bytes = self.reader.read_at(0, len).await?;
         match String::from_utf8(bytes.to_vec()) {
             Ok(content) => Ok(content),
             Err(e) => Err(Error::FileSystemError(FileSystemError::InvalidUtf8(e))),
@@ -188,7 +267,7 @@ impl File {
         if re.is_match(&content) {
             return Ok(())
         }
-        Err(Error::FileSystemError(FileSystemError::SearchError(content.into())))
+        Err(Error::FileSystemError(FileSystemError::SearchError(content)))
     }
 
     pub async fn contains(&self, content: &String) -> Result<()> {
@@ -209,8 +288,14 @@ impl File {
         Err(Error::FileSystemError(FileSystemError::SearchError(content.into())))
     }
 
+    /// Size in bytes as of the last write or read of the content through this file
     pub fn size(&self) -> u64 {
-        self.metadata.len()
+        self.size.load(Ordering::Relaxed)
+    }
+
+    async fn refresh_size(&self) -> Result<()> {
+        self.size.store(self.writer.len().await?, Ordering::Relaxed);
+        Ok(())
     }
 
     pub async fn size_bits(&self) -> u64 {
@@ -234,23 +319,32 @@ impl File {
     }
 }
 
-fn name(path: &String) -> Result<String> {
+/// Registers `target` under a fresh id.
+fn register(target: Target) -> Result<(TargetHandle<Target>, BufferWriter<Target>, BufferReader<Target>)> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let handle = IO_REGISTRY.insert(id, target, Duration::from_millis(1000), Duration::from_millis(1000))?;
+    Ok((handle, IO_REGISTRY.writer(&handle)?, IO_REGISTRY.reader(&handle)?))
+}
+
+fn name(path: &str) -> Result<String> {
     if let Some(pos) = path.rfind("/") {
         if let Some(pos_ext) = path.rfind(".") {
             return Ok(path[pos..pos_ext].to_string())
         }
-        return Ok(path[pos..].to_string())
+        Ok(path[pos..].to_string())
     } else if let Some(pos) = path.rfind("\\") {
         if let Some(pos_ext) = path.rfind(".") {
             return Ok(path[pos..pos_ext].to_string())
         }
-        return Ok(path[pos..].to_string())
+        Ok(path[pos..].to_string())
     } else {
         Err(Error::Internal("Failed to get name from path".to_string()))
     }
 }
 
-fn extension(path: &String) -> Result<String> {
+fn extension(path: &str) -> Result<String> {
     if let Some(pos) = path.rfind(".") {
         return Ok(path[pos..].to_string())
     }
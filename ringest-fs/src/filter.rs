@@ -45,6 +45,12 @@ pub struct FilterBuilder {
     filter: Filter
 }
 
+impl Default for FilterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FilterBuilder {
     pub fn new() -> Self {
         Self {
@@ -185,45 +191,45 @@ impl Filter {
 
     #[inline]
     pub fn check_modified(&self, file_time: SystemTime) -> bool {
-        if let Some(after) = self.modified_after {
-            if file_time < after {
-                return false
-            }
+        if let Some(after) = self.modified_after
+            && file_time < after
+        {
+            return false;
         }
-        if let Some(before) = self.modified_before {
-            if file_time > before {
-                return false
-            }
+        if let Some(before) = self.modified_before
+            && file_time > before
+        {
+            return false;
         }
         true
     }
 
     #[inline]
     pub fn check_accessed(&self, file_time: SystemTime) -> bool {
-        if let Some(after) = self.accessed_after {
-            if file_time < after {
-                return false
-            }
+        if let Some(after) = self.accessed_after
+            && file_time < after
+        {
+            return false;
         }
-        if let Some(before) = self.accessed_before {
-            if file_time > before {
-                return false
-            }
+        if let Some(before) = self.accessed_before
+            && file_time > before
+        {
+            return false;
         }
         true
     }
 
     #[inline]
     pub fn check_created(&self, file_time: SystemTime) -> bool {
-        if let Some(after) = self.created_after {
-            if file_time < after {
-                return false
-            }
+        if let Some(after) = self.created_after
+            && file_time < after
+        {
+            return false;
         }
-        if let Some(before) = self.created_before {
-            if file_time > before {
-                return false
-            }
+        if let Some(before) = self.created_before
+            && file_time > before
+        {
+            return false;
         }
         true
     }
@@ -256,21 +262,19 @@ impl Filter {
     pub fn allows(&self, entry: &tokio::fs::DirEntry, metadata: &Metadata) -> bool {
         let name = entry.file_name().to_string_lossy().to_string();
 
-        if let Some(ref target) = self.target_name { if &name != target { return false } }
-        if let Some(ref prefix) = self.name_prefix { if !name.starts_with(prefix) { return false } }
-        if let Some(ref suffix) = self.name_suffix { if !name.ends_with(suffix) { return false } }
+        if let Some(ref target) = self.target_name && &name != target { return false }
+        if let Some(ref prefix) = self.name_prefix && !name.starts_with(prefix) { return false }
+        if let Some(ref suffix) = self.name_suffix && !name.ends_with(suffix) { return false }
 
-        if let Some(ref ext) = self.extension {
-            if !name.ends_with(&format!(".{}", ext)) { return false }
-        }
+        if let Some(ref ext) = self.extension
+            && !name.ends_with(&format!(".{}", ext)) { return false }
 
-        if let Some(ref excludes) = self.exclude_dirs {
-            if entry.path().is_dir() && excludes.contains(&name) { return false }
-        }
+        if let Some(ref excludes) = self.exclude_dirs
+            && entry.path().is_dir() && excludes.contains(&name) { return false }
 
         let size = metadata.len();
-        if let Some(max) = self.max_size { if size > max { return false } }
-        if let Some(min) = self.min_size { if size < min { return false } }
+        if let Some(max) = self.max_size && size > max { return false }
+        if let Some(min) = self.min_size && size < min { return false }
 
         if !self.include_hidden && name.starts_with(".") {
             return false
@@ -280,15 +284,12 @@ impl Filter {
             return false
         }
 
-        if let Ok(accessed) = metadata.accessed() {
-            if !self.check_accessed(accessed) { return false }
-        }
-        if let Ok(modified) = metadata.modified() {
-            if !self.check_modified(modified) { return false }
-        }
-        if let Ok(created) = metadata.created() {
-            if !self.check_created(created) { return false }
-        }
+        if let Ok(accessed) = metadata.accessed()
+            && !self.check_accessed(accessed) { return false }
+        if let Ok(modified) = metadata.modified()
+            && !self.check_modified(modified) { return false }
+        if let Ok(created) = metadata.created()
+            && !self.check_created(created) { return false }
 
         true
     }
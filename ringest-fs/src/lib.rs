@@ -1,4 +1,3 @@
-use dashmap::DashMap;
 use ringest_io::Registry;
 
 pub mod filter;
@@ -7,6 +6,4 @@ pub mod dir;
 
 lazy_static::lazy_static! {
     static ref IO_REGISTRY: Registry = Registry::new();
-    /// File ID - (name, path)
-    static ref REGISTERED_FILES: DashMap<u64, (String, String)> = DashMap::new();
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, fs::File, io::Read, path::Path, sync::Arc};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use parking_lot::Mutex;
+use ringest_error::{Error, Result};
+use crate::{Registry, wal};
+
+/// Journaled writes: target id, offset and data
+type Entry = (u64, u64, Bytes);
+
+/// Writes to several registered targets that land on all of them or on none.
+///
+/// Committing is two-phase. First every target in the batch flushes its queue and holds
+/// off further flushes, then the whole batch is appended to the registry journal and
+/// synced; that record is the commit point. Only then is each target written and synced,
+/// after which the journal is cleared. A crash before the record is complete leaves every
+/// target untouched, and one after it is repaired by [`Registry::recover_batches`].
+///
+/// A write that fails for good is handed to the target's dead-letter handler like any
+/// other, so with a handler configured a batch can end up applied in part.
+#[must_use = "a batch does nothing unless committed"]
+pub struct Batch<'a> {
+    registry: &'a Registry,
+    /// Staged writes by target id
+    writes: BTreeMap<u64, Vec<(u64, Bytes)>>,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(registry: &'a Registry) -> Self {
+        Self {
+            registry,
+            writes: BTreeMap::new(),
+        }
+    }
+
+    /// Stages a write to target `id`. Where staged writes to one target overlap, the later one wins.
+    pub fn write_at(&mut self, id: u64, offset: u64, data: impl Into<Bytes>) -> &mut Self {
+        self.writes.entry(id).or_default().push((offset, data.into()));
+        self
+    }
+
+    /// Number of staged writes
+    pub fn len(&self) -> usize {
+        self.writes.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Writes every staged write to its target, failing without touching any of them if
+    /// the registry has no journal or one of the ids is not registered.
+    pub async fn commit(self) -> Result<()> {
+        if self.is_empty() { return Ok(()); }
+        let journal = self.registry.journal()?;
+        let _gate = journal.gate.lock().await;
+        replay(self.registry, &journal).await?;
+
+        let participants = self.registry.contexts(self.writes.keys())?;
+        let mut guards = Vec::with_capacity(participants.len());
+        for participant in &participants {
+            guards.push(participant.prepare_batch(true).await?);
+        }
+
+        let entries: Vec<Entry> = self.writes.iter()
+            .flat_map(|(id, writes)| writes.iter().map(|(offset, data)| (*id, *offset, data.clone())))
+            .collect();
+        journal.log(entries).await?;
+
+        // Past the commit point: a failure from here on leaves the batch in the journal.
+        for (participant, writes) in participants.iter().zip(self.writes.into_values()) {
+            participant.apply_batch(writes).await?;
+        }
+        journal.clear().await
+    }
+
+    /// Discards the staged writes, same as dropping the batch.
+    pub fn rollback(self) {}
+}
+
+/// Writes the batches left in `journal` to their targets and clears it.
+/// Called with the journal gate held.
+pub(crate) async fn replay(registry: &Registry, journal: &Journal) -> Result<()> {
+    let pending = journal.pending.lock().clone();
+    if pending.is_empty() { return Ok(()); }
+
+    let mut writes: BTreeMap<u64, Vec<(u64, Bytes)>> = BTreeMap::new();
+    for (id, offset, data) in pending.into_iter().flatten() {
+        writes.entry(id).or_default().push((offset, data));
+    }
+
+    // Whatever the targets still have queued was written after these batches, so it stays on top.
+    let participants = registry.contexts(writes.keys())?;
+    let mut guards = Vec::with_capacity(participants.len());
+    for participant in &participants {
+        guards.push(participant.prepare_batch(false).await?);
+    }
+    for (participant, writes) in participants.iter().zip(writes.into_values()) {
+        participant.apply_batch(writes).await?;
+    }
+    journal.clear().await
+}
+
+/// Registry-wide log of committed batches that may not have reached every target yet.
+///
+/// Each batch is one CRC-checked record, framed like the records of a `WriteAheadLog`,
+/// so a batch torn by a crash is dropped whole.
+pub(crate) struct Journal {
+    file: Arc<Mutex<File>>,
+    /// Held across a whole commit or recovery, so batches are applied one at a time
+    pub(crate) gate: tokio::sync::Mutex<()>,
+    /// Batches in the file, oldest first
+    pending: Mutex<Vec<Vec<Entry>>>,
+}
+
+impl Journal {
+    /// Opens (or creates) the journal at `path`, cutting off a torn tail.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+
+        let mut pending = Vec::new();
+        let mut valid_len = 0;
+        for (payload, end) in wal::frames(&raw) {
+            let Some(batch) = decode(payload) else { break };
+            pending.push(batch);
+            valid_len = end;
+        }
+        if valid_len < raw.len() {
+            file.set_len(valid_len as u64)?;
+            file.sync_data()?;
+        }
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            gate: tokio::sync::Mutex::new(()),
+            pending: Mutex::new(pending),
+        })
+    }
+
+    /// Appends `entries` as one record and waits until it is durable.
+    async fn log(&self, entries: Vec<Entry>) -> Result<()> {
+        let mut payload = BytesMut::new();
+        payload.put_u64_le(entries.len() as u64);
+        for (id, offset, data) in &entries {
+            payload.put_u64_le(*id);
+            payload.put_u64_le(*offset);
+            payload.put_u64_le(data.len() as u64);
+            payload.put_slice(data);
+        }
+        let mut record = BytesMut::new();
+        wal::frame(&mut record, &payload);
+
+        let file = Arc::clone(&self.file);
+        tokio::task::spawn_blocking(move || wal::append_durably(&mut file.lock(), &record))
+            .await.map_err(|e| Error::Internal(e.to_string()))??;
+
+        self.pending.lock().push(entries);
+        Ok(())
+    }
+
+    /// Empties the journal once every batch in it has reached its targets.
+    async fn clear(&self) -> Result<()> {
+        let file = Arc::clone(&self.file);
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let file = file.lock();
+            file.set_len(0)?;
+            file.sync_data()
+        }).await.map_err(|e| Error::Internal(e.to_string()))??;
+
+        self.pending.lock().clear();
+        Ok(())
+    }
+}
+
+/// Entries of a batch record, or `None` if it is malformed.
+fn decode(mut cursor: &[u8]) -> Option<Vec<Entry>> {
+    if cursor.remaining() < 8 { return None; }
+    let count = cursor.get_u64_le();
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if cursor.remaining() < 24 { return None; }
+        let id = cursor.get_u64_le();
+        let offset = cursor.get_u64_le();
+        let len = cursor.get_u64_le() as usize;
+        if cursor.remaining() < len { return None; }
+        entries.push((id, offset, Bytes::copy_from_slice(&cursor[..len])));
+        cursor.advance(len);
+    }
+    Some(entries)
+}
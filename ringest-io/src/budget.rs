@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Notify;
+
+/// What `write_at` does when a queued write would exceed a memory budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackpressureMode {
+    /// Flush, then wait for capacity for up to the write timeout
+    #[default]
+    Wait,
+    /// Fail immediately with `Error::QueueFull`
+    FailFast,
+}
+
+/// Byte budget for queued writes shared by every target of a `Registry`.
+///
+/// A write larger than the whole budget is still admitted when nothing else is queued,
+/// so an oversized write can never wait forever.
+pub struct MemoryBudget {
+    limit: u64,
+    used: AtomicU64,
+    pub(crate) released: Notify,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: AtomicU64::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Bytes currently queued across all targets
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn try_acquire(&self, bytes: u64) -> bool {
+        self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            if used > 0 && used + bytes > self.limit { None } else { Some(used + bytes) }
+        }).is_ok()
+    }
+
+    /// Takes `bytes` regardless of the limit, for writes that are already queued.
+    pub(crate) fn force_acquire(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    pub(crate) fn release(&self, bytes: u64) {
+        self.used.fetch_sub(bytes, Ordering::AcqRel);
+        self.released.notify_waiters();
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, sync::atomic::{AtomicU64, Ordering}};
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+struct Slot {
+    key: (u64, u64),
+    data: Bytes,
+    referenced: bool,
+}
+
+#[derive(Default)]
+struct Clock {
+    index: HashMap<(u64, u64), usize>,
+    slots: Vec<Option<Slot>>,
+    free: Vec<usize>,
+    hand: usize,
+}
+
+impl Clock {
+    fn remove(&mut self, key: &(u64, u64)) {
+        if let Some(i) = self.index.remove(key) {
+            self.slots[i] = None;
+            self.free.push(i);
+        }
+    }
+
+    /// Finds a slot for a new block, evicting the first unreferenced one the hand meets.
+    fn victim(&mut self, capacity: usize) -> usize {
+        if let Some(i) = self.free.pop() {
+            return i;
+        }
+        if self.slots.len() < capacity {
+            self.slots.push(None);
+            return self.slots.len() - 1;
+        }
+
+        loop {
+            let i = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            match &mut self.slots[i] {
+                Some(slot) if slot.referenced => slot.referenced = false,
+                Some(slot) => {
+                    self.index.remove(&slot.key);
+                    return i;
+                }
+                None => return i,
+            }
+        }
+    }
+}
+
+/// Page-aligned cache of target blocks, shared by every target of a `Registry`.
+///
+/// Eviction follows the CLOCK algorithm, an approximation of LRU: a block read since
+/// the hand last passed it gets a second chance.
+pub struct BlockCache {
+    block_size: usize,
+    capacity: usize,
+    clock: Mutex<Clock>,
+    next_owner: AtomicU64,
+}
+
+impl BlockCache {
+    /// Cache holding up to `capacity` bytes in blocks of `block_size` bytes.
+    pub fn new(capacity: u64, block_size: usize) -> Self {
+        let block_size = block_size.max(1);
+        Self {
+            block_size,
+            capacity: (capacity / block_size as u64) as usize,
+            clock: Mutex::new(Clock::default()),
+            next_owner: AtomicU64::new(1),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Capacity in blocks
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of cached blocks
+    pub fn len(&self) -> usize {
+        self.clock.lock().index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Id that keeps one target's blocks apart from the others'.
+    pub(crate) fn register(&self) -> u64 {
+        self.next_owner.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub(crate) fn get(&self, owner: u64, block: u64) -> Option<Bytes> {
+        let mut clock = self.clock.lock();
+        let i = *clock.index.get(&(owner, block))?;
+        let slot = clock.slots[i].as_mut()?;
+        slot.referenced = true;
+        Some(slot.data.clone())
+    }
+
+    /// Caches the whole blocks in `data`, which starts at block `first`, unless
+    /// `still_valid` says the target changed since `data` was read.
+    pub(crate) fn fill(&self, owner: u64, first: u64, data: &Bytes, still_valid: impl FnOnce() -> bool) {
+        if self.capacity == 0 { return; }
+
+        let mut clock = self.clock.lock();
+        if !still_valid() { return; }
+
+        for i in 0..data.len() / self.block_size {
+            let start = i * self.block_size;
+            let key = (owner, first + i as u64);
+            clock.remove(&key);
+            let slot = clock.victim(self.capacity);
+            clock.slots[slot] = Some(Slot { key, data: data.slice(start..start + self.block_size), referenced: false });
+            clock.index.insert(key, slot);
+        }
+    }
+
+    /// Drops the blocks of `owner` that intersect `[offset, end)`.
+    pub(crate) fn invalidate(&self, owner: u64, offset: u64, end: u64) {
+        if end <= offset { return; }
+
+        let first = offset / self.block_size as u64;
+        let last = (end - 1) / self.block_size as u64;
+        let mut clock = self.clock.lock();
+
+        if last - first < clock.index.len() as u64 {
+            for block in first..=last {
+                clock.remove(&(owner, block));
+            }
+        } else {
+            let keys: Vec<_> = clock.index.keys()
+                .filter(|(o, b)| *o == owner && (first..=last).contains(b))
+                .copied()
+                .collect();
+            for key in keys {
+                clock.remove(&key);
+            }
+        }
+    }
+
+    /// Drops every block of `owner`.
+    pub(crate) fn purge(&self, owner: u64) {
+        let mut clock = self.clock.lock();
+        let keys: Vec<_> = clock.index.keys().filter(|(o, _)| *o == owner).copied().collect();
+        for key in keys {
+            clock.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use parking_lot::RwLock;
+use crate::{Durability, budget::BackpressureMode, retry::{DeadLetterHandler, RetryPolicy}};
+
+/// Per-target settings used by `Registry::insert_with`.
+#[derive(Debug, Clone)]
+pub struct IoConfig {
+    pub(crate) write_timeout: Duration,
+    pub(crate) read_timeout: Duration,
+    pub(crate) latency_threshold_ns: u64,
+    pub(crate) small_write_cutoff: usize,
+    pub(crate) max_queue_bytes: u64,
+    pub(crate) max_queue_ops: usize,
+    pub(crate) coalesce_gap: u64,
+    pub(crate) block_cache: bool,
+    pub(crate) readahead: u64,
+    pub(crate) durability: Durability,
+    pub(crate) wal_path: Option<PathBuf>,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) dead_letter: Option<DeadLetterHandler>,
+    pub(crate) memory_budget: Option<u64>,
+    pub(crate) backpressure: BackpressureMode,
+    pub(crate) snapshot_limit: u64,
+}
+
+impl Default for IoConfig {
+    fn default() -> Self {
+        Self {
+            write_timeout: Duration::from_millis(1000),
+            read_timeout: Duration::from_millis(1000),
+            latency_threshold_ns: 1_000_000,
+            small_write_cutoff: 4 * 1024,
+            max_queue_bytes: 16 * 1024,
+            max_queue_ops: usize::MAX,
+            coalesce_gap: 0,
+            block_cache: false,
+            readahead: 0,
+            durability: Durability::None,
+            wal_path: None,
+            retry: RetryPolicy::default(),
+            dead_letter: None,
+            memory_budget: None,
+            backpressure: BackpressureMode::Wait,
+            snapshot_limit: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl IoConfig {
+    pub fn builder() -> IoConfigBuilder {
+        IoConfigBuilder::new()
+    }
+
+    pub fn write_timeout(&self) -> Duration {
+        self.write_timeout
+    }
+
+    pub fn read_timeout(&self) -> Duration {
+        self.read_timeout
+    }
+
+    pub fn latency_threshold_ns(&self) -> u64 {
+        self.latency_threshold_ns
+    }
+
+    pub fn small_write_cutoff(&self) -> usize {
+        self.small_write_cutoff
+    }
+
+    pub fn max_queue_bytes(&self) -> u64 {
+        self.max_queue_bytes
+    }
+
+    pub fn max_queue_ops(&self) -> usize {
+        self.max_queue_ops
+    }
+
+    pub fn coalesce_gap(&self) -> u64 {
+        self.coalesce_gap
+    }
+
+    pub fn block_cache(&self) -> bool {
+        self.block_cache
+    }
+
+    pub fn readahead(&self) -> u64 {
+        self.readahead
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub fn wal_path(&self) -> Option<&PathBuf> {
+        self.wal_path.as_ref()
+    }
+
+    pub fn retry(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    pub fn dead_letter(&self) -> Option<&DeadLetterHandler> {
+        self.dead_letter.as_ref()
+    }
+
+    pub fn memory_budget(&self) -> Option<u64> {
+        self.memory_budget
+    }
+
+    pub fn backpressure(&self) -> BackpressureMode {
+        self.backpressure
+    }
+
+    pub fn snapshot_limit(&self) -> u64 {
+        self.snapshot_limit
+    }
+}
+
+pub struct IoConfigBuilder {
+    config: IoConfig,
+}
+
+impl Default for IoConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoConfigBuilder {
+    pub fn new() -> Self {
+        Self {
+            config: IoConfig::default(),
+        }
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    /// Average write latency above which every write is queued, whatever its size
+    pub fn latency_threshold_ns(mut self, threshold: u64) -> Self {
+        self.config.latency_threshold_ns = threshold;
+        self
+    }
+
+    /// Writes shorter than this are queued instead of going straight to the target
+    pub fn small_write_cutoff(mut self, bytes: usize) -> Self {
+        self.config.small_write_cutoff = bytes;
+        self
+    }
+
+    /// Queue size in bytes that triggers an automatic flush
+    pub fn max_queue_bytes(mut self, bytes: u64) -> Self {
+        self.config.max_queue_bytes = bytes;
+        self
+    }
+
+    /// Number of queued writes that triggers an automatic flush
+    pub fn max_queue_ops(mut self, ops: usize) -> Self {
+        self.config.max_queue_ops = ops;
+        self
+    }
+
+    /// Largest hole between queued writes that a flush fills from the target
+    /// to send both sides as one write; 0 only merges adjacent writes
+    pub fn coalesce_gap(mut self, bytes: u64) -> Self {
+        self.config.coalesce_gap = bytes;
+        self
+    }
+
+    /// Keeps blocks read from this target in the registry's block cache, if it has one
+    pub fn block_cache(mut self, enabled: bool) -> Self {
+        self.config.block_cache = enabled;
+        self
+    }
+
+    /// Largest window prefetched ahead of sequential reads; 0 turns readahead off
+    pub fn readahead(mut self, max_window: u64) -> Self {
+        self.config.readahead = max_window;
+        self
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.config.durability = durability;
+        self
+    }
+
+    /// Records queued writes in a write-ahead log at `path`. Only read at insert time.
+    pub fn wal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.wal_path = Some(path.into());
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry = policy;
+        self
+    }
+
+    pub fn dead_letter(mut self, handler: DeadLetterHandler) -> Self {
+        self.config.dead_letter = Some(handler);
+        self
+    }
+
+    /// Most bytes this target may hold in its queue; unlimited by default
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.config.memory_budget = Some(bytes);
+        self
+    }
+
+    pub fn backpressure(mut self, mode: BackpressureMode) -> Self {
+        self.config.backpressure = mode;
+        self
+    }
+
+    /// Most bytes a snapshot may copy from the target before it expires; 64 MiB by default
+    pub fn snapshot_limit(mut self, bytes: u64) -> Self {
+        self.config.snapshot_limit = bytes;
+        self
+    }
+
+    pub fn build(self) -> IoConfig {
+        self.config
+    }
+}
+
+/// Shared view of a target's live configuration.
+///
+/// Changes made through the setters apply to the next operation on the target.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    inner: Arc<RwLock<IoConfig>>,
+}
+
+impl ConfigHandle {
+    pub(crate) fn new(config: IoConfig) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    pub fn get(&self) -> IoConfig {
+        self.inner.read().clone()
+    }
+
+    pub fn set_write_timeout(&self, timeout: Duration) {
+        self.inner.write().write_timeout = timeout;
+    }
+
+    pub fn set_read_timeout(&self, timeout: Duration) {
+        self.inner.write().read_timeout = timeout;
+    }
+
+    pub fn set_latency_threshold_ns(&self, threshold: u64) {
+        self.inner.write().latency_threshold_ns = threshold;
+    }
+
+    pub fn set_small_write_cutoff(&self, bytes: usize) {
+        self.inner.write().small_write_cutoff = bytes;
+    }
+
+    pub fn set_max_queue_bytes(&self, bytes: u64) {
+        self.inner.write().max_queue_bytes = bytes;
+    }
+
+    pub fn set_max_queue_ops(&self, ops: usize) {
+        self.inner.write().max_queue_ops = ops;
+    }
+
+    pub fn set_coalesce_gap(&self, bytes: u64) {
+        self.inner.write().coalesce_gap = bytes;
+    }
+
+    pub fn set_block_cache(&self, enabled: bool) {
+        self.inner.write().block_cache = enabled;
+    }
+
+    pub fn set_readahead(&self, max_window: u64) {
+        self.inner.write().readahead = max_window;
+    }
+
+    pub fn set_durability(&self, durability: Durability) {
+        self.inner.write().durability = durability;
+    }
+
+    pub fn set_retry(&self, policy: RetryPolicy) {
+        self.inner.write().retry = policy;
+    }
+
+    pub fn set_dead_letter(&self, handler: Option<DeadLetterHandler>) {
+        self.inner.write().dead_letter = handler;
+    }
+
+    pub fn set_memory_budget(&self, bytes: Option<u64>) {
+        self.inner.write().memory_budget = bytes;
+    }
+
+    pub fn set_backpressure(&self, mode: BackpressureMode) {
+        self.inner.write().backpressure = mode;
+    }
+
+    /// Applies to snapshots taken after the call
+    pub fn set_snapshot_limit(&self, bytes: u64) {
+        self.inner.write().snapshot_limit = bytes;
+    }
+
+    pub(crate) fn read(&self) -> parking_lot::RwLockReadGuard<'_, IoConfig> {
+        self.inner.read()
+    }
+}
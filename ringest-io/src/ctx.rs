@@ -1,32 +1,115 @@
-use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
-use bytes::{BufMut, Bytes, BytesMut};
+use std::{any::Any, sync::{Arc, Weak, atomic::{AtomicBool, AtomicU64, Ordering}}, time::Duration};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
 use parking_lot::RwLock;
-use tokio::sync::{Mutex, Notify};
-use crate::{IoMetrics, IoTarget, IoTimeoutExt, LatencyMeasureExt, PendingRead, PendingWrite, TIME_CACHE, WriteQueue, time::TimeCache};
-use ringest_error::Result;
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard};
+use crate::{Durability, IoMetrics, IoTarget, MetricsReport, IoTimeoutExt, LatencyMeasureExt, PendingRead, PendingWrite, SyncMode, TIME_CACHE, WriteQueue};
+use crate::{budget::{BackpressureMode, MemoryBudget}, config::ConfigHandle, wal::WriteAheadLog};
+use crate::{cache::BlockCache, interval::{IntervalMap, Run}, readahead::Readahead, retry::{DeadLetter, RetryPolicy}, snapshot::SnapshotState, trace};
+use ringest_error::{Error, Result, TargetError};
 
 pub struct IoContext<T: IoTarget> {
+    /// Id the target is registered under
+    pub id: u64,
+    /// Registry-wide number of this registration of `id`
+    pub generation: u64,
     pub target: Arc<T>,
     pub metrics: Arc<IoMetrics>,
     pub write_queue: Arc<RwLock<WriteQueue>>,
     pub read_queue: Arc<RwLock<Vec<PendingRead>>>,
     pub flushing_queue: Arc<RwLock<WriteQueue>>,
-    pub write_timeout: Duration,
-    pub read_timeout: Duration,
-    pub threshold_ns: u64,
+    pub config: ConfigHandle,
     pub flush_lock: Arc<Mutex<()>>,
+    /// Shared by direct writes, exclusive while a flush writes, so that gaps
+    /// filled from the target cannot change before they are written back
+    pub target_lock: tokio::sync::RwLock<()>,
+    /// Sequence number of the next queued write
+    pub next_seq: AtomicU64,
+    pub wal: Option<Arc<WriteAheadLog>>,
+    /// Writes that reached the target since the last sync
+    pub unsynced: AtomicU64,
+    /// Registry-wide budget shared with every other target
+    pub budget: Option<Arc<MemoryBudget>>,
+    /// Woken whenever queued bytes of this target are released
+    pub capacity: Notify,
+    /// Registry-wide block cache, used for reads when the config enables it
+    pub cache: Option<Arc<BlockCache>>,
+    /// Tells this target's blocks apart from other targets' in the cache
+    pub cache_owner: u64,
+    /// Bumped whenever the target is written, so data read before that is not reused
+    pub write_epoch: AtomicU64,
+    pub(crate) readahead: Readahead,
+    /// Snapshots to copy old contents into before the target is overwritten
+    pub(crate) snapshots: parking_lot::Mutex<Vec<Weak<SnapshotState>>>,
+    /// Set by `close`; writes fail from then on
+    pub(crate) closed: AtomicBool,
+    /// Writes under way, which `close` waits for
+    pub(crate) writers: AtomicU64,
+    /// Woken when the last write under way finishes
+    pub(crate) writers_done: Notify,
+}
+
+/// A write under way, counted until dropped.
+struct WriteGuard<'a> {
+    writers: &'a AtomicU64,
+    done: &'a Notify,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        if self.writers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.done.notify_waiters();
+        }
+    }
+}
+
+/// Why the queue is being flushed; decides whether the durability policy syncs afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncPoint {
+    /// Size threshold, janitor or writer drop
+    Auto,
+    /// `BufferWriter::flush`
+    Flush,
+    /// `BufferWriter::shutdown`
+    Shutdown,
 }
 
 impl<T: IoTarget> IoContext<T> {
     pub async fn flush(&self) -> Result<()> {
+        self.flush_queue(SyncPoint::Flush).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "flush", skip_all, fields(
+        target_id = self.id, reason = ?point, len = tracing::field::Empty,
+        queue_depth = tracing::field::Empty, outcome = tracing::field::Empty, error = tracing::field::Empty,
+    )))]
+    pub(crate) async fn flush_queue(&self, point: SyncPoint) -> Result<()> {
+        let start = minstant::Instant::now();
         let _guard = self.flush_lock.lock().await;
+        trace::event!("flush lock acquired");
+        let drained = self.drain_queue().await;
+        let idle = matches!(drained, Ok(0));
+        let result = match drained {
+            Ok(bytes) => self.sync_for(point).await.map(|()| bytes),
+            Err(e) => Err(e),
+        };
+        trace::finish(&result, || self.queue_depth());
+        if !idle {
+            self.metrics.record_flush(start, *result.as_ref().unwrap_or(&0), &result);
+        }
+        result.map(|_| ())
+    }
 
-        let (mut q, total_bytes) = {
+    /// Writes the queue out to the target and returns how many queued bytes it held.
+    async fn drain_queue(&self) -> Result<u64> {
+        let (q, taken) = {
             let mut w_lock = self.write_queue.write();
-            if w_lock.is_empty() { return Ok(()); }
+            if w_lock.is_empty() { return Ok(0); }
             
             let data = std::mem::take(&mut *w_lock);
-            let bytes = data.total_bytes;
+            let bytes = data.total_bytes();
+            trace::record!("len", bytes);
+            trace::event!(segments = data.len(), "queue taken");
             
             let mut f_lock = self.flushing_queue.write();
             *f_lock = data.clone();
@@ -34,131 +117,912 @@ impl<T: IoTarget> IoContext<T> {
             (data, bytes)
         };
 
-        q.writes.sort_by_key(|op| op.offset);
-        let mut it = q.writes.into_iter().peekable();
-        let mut combined_buffer = BytesMut::with_capacity(total_bytes as usize);
+        let (write_timeout, read_timeout, coalesce_gap, retry, dead_letter) = {
+            let config = self.config.read();
+            (config.write_timeout, config.read_timeout, config.coalesce_gap, config.retry.clone(), config.dead_letter.clone())
+        };
+        let runs = q.into_runs(coalesce_gap);
+
+        let exclusive = self.target_lock.write().await;
+        let mut batch = Vec::with_capacity(runs.len());
+        for run in runs {
+            match self.assemble(&run, &retry, read_timeout).await {
+                Ok(writes) => batch.push((run, writes)),
+                // Gaps could not be read, so the pieces go out on their own.
+                Err(_) => batch.extend(run.split().into_iter().map(|piece| {
+                    let writes = piece.writes();
+                    (piece, writes)
+                })),
+            }
+        }
+
+        let segments = batch.iter().map(|(run, _)| run.segments.len() as u64).sum();
+        trace::event!(segments, runs = batch.len(), "runs assembled");
+        self.metrics.flushed_segments.fetch_add(segments, Ordering::Relaxed);
+        self.metrics.flushed_runs.fetch_add(batch.len() as u64, Ordering::Relaxed);
 
-        while let Some(current) = it.next() {
-            combined_buffer.clear();
-            combined_buffer.put(&current.data[..]);
-            let start_offset = current.offset;
+        let ranges: Vec<(u64, usize)> = batch.iter().map(|(run, _)| (run.offset, (run.end - run.offset) as usize)).collect();
+        self.preserve(&ranges).await;
+        let written = batch.len();
+        let failed = self.write_runs(batch, &retry, write_timeout).await;
+        trace::event!(failed = failed.len(), "runs written");
+        if !failed.is_empty() {
+            match &dead_letter {
+                Some(handler) => for (run, writes, error) in failed {
+                    handler.handle(DeadLetter { offset: run.offset, data: join(&writes), error });
+                },
+                None => {
+                    // Requeued with their old sequence numbers, so writes queued since then still win.
+                    // Only the queued segments go back; gap contents are read again next time.
+                    let failed_runs = failed.len();
+                    let mut requeued: u64 = failed.iter().map(|(run, ..)| run.queued_bytes()).sum();
+                    let mut errors = Vec::with_capacity(failed.len());
+                    {
+                        let mut w_lock = self.write_queue.write();
+                        for (run, _, error) in failed {
+                            for op in run.segments {
+                                requeued -= w_lock.push(op);
+                            }
+                            errors.push(error);
+                        }
+                    }
+                    drop(exclusive);
 
-            while let Some(next) = it.peek() {
-                if start_offset + combined_buffer.len() as u64 == next.offset {
-                    combined_buffer.put(&next.data[..]);
-                    it.next();
-                } else { break; }
+                    // Runs that did land leave the log, or recovery would replay them over
+                    // later writes that went straight to the target.
+                    if failed_runs < written {
+                        self.unsynced.fetch_add(1, Ordering::Relaxed);
+                        let _ = self.compact_wal().await;
+                    }
+                    self.flushing_queue.write().clear();
+                    self.release(taken - requeued);
+                    return Err(errors.swap_remove(0));
+                }
             }
-            self.target.write_at(combined_buffer.split().freeze(), start_offset).await?;
         }
+        drop(exclusive);
+        self.unsynced.fetch_add(1, Ordering::Relaxed);
 
+        let result = self.compact_wal().await;
         self.flushing_queue.write().clear();
+        self.release(taken);
+        result?;
+
         self.metrics.last_out.store(TIME_CACHE.get_cached(), Ordering::Relaxed);
+        Ok(taken)
+    }
+
+    /// Sends `batch` to the target in one go, then retries the runs that failed as the
+    /// policy allows. Returns the runs that failed for good, in offset order.
+    async fn write_runs(&self, mut batch: Vec<(Run, Vec<(u64, Bytes)>)>, retry: &RetryPolicy, write_timeout: Duration) -> Vec<(Run, Vec<(u64, Bytes)>, Error)> {
+        let mut failed = Vec::new();
+        let mut attempt = 1;
+
+        while !batch.is_empty() {
+            trace::event!(attempt, runs = batch.len(), "writing runs");
+            let writes: Vec<Vec<(u64, Bytes)>> = batch.iter().map(|(_, writes)| writes.clone()).collect();
+            let mut results = async { Ok(self.target.write_batch_at(&writes).await) }
+                .with_timeout(write_timeout)
+                .await
+                .unwrap_or_default();
+            results.resize_with(writes.len(), || Err(Error::Timeout));
+
+            let mut again = Vec::new();
+            for ((run, writes), result) in batch.into_iter().zip(results) {
+                self.invalidate(run.offset, run.end);
+                match result {
+                    Ok(()) => {}
+                    Err(e) if attempt < retry.max_attempts() && retry.is_retriable(&e) => again.push((run, writes)),
+                    Err(e) => failed.push((run, writes, e)),
+                }
+            }
+
+            if !again.is_empty() {
+                tokio::time::sleep(retry.delay_for(attempt)).await;
+            }
+            attempt += 1;
+            batch = again;
+        }
+
+        failed.sort_by_key(|(run, ..)| run.offset);
+        failed
+    }
+
+    /// Contents of `run` as adjacent `(offset, data)` pairs, with its gaps read from
+    /// the target in one vectored read. Queued data is never copied.
+    async fn assemble(&self, run: &Run, retry: &RetryPolicy, read_timeout: Duration) -> Result<Vec<(u64, Bytes)>> {
+        let gaps = run.gaps();
+        if gaps.is_empty() {
+            return Ok(run.writes());
+        }
+
+        let current = retry.run(|| {
+            self.target.read_vectored_at(&gaps).with_timeout(read_timeout)
+        }).await?;
+
+        let mut fills = gaps.iter().zip(current).map(|((offset, len), data)| (*offset, pad(data, *len)));
+        let mut writes = Vec::with_capacity(run.segments.len() + gaps.len());
+        let mut end = run.offset;
+        for op in &run.segments {
+            if op.offset > end {
+                writes.extend(fills.next());
+            }
+            writes.push((op.offset, op.data.clone()));
+            end = op.offset + op.data.len() as u64;
+        }
+        Ok(writes)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn compact_wal(&self) -> Result<()> {
+        let Some(wal) = &self.wal else { return Ok(()) };
+
+        // The log is the only durable copy until the target itself is synced.
+        let mode = match self.config.read().durability {
+            Durability::FullSync => SyncMode::All,
+            _ => SyncMode::Data,
+        };
+        self.sync(mode).await?;
+
+        let _gate = wal.gate.lock().await;
+        let remaining: Vec<PendingWrite> = self.write_queue.read().iter().collect();
+        wal.reset(&remaining).await
+    }
+
+    async fn sync_for(&self, point: SyncPoint) -> Result<()> {
+        let unsynced = self.unsynced.load(Ordering::Relaxed);
+        if unsynced == 0 { return Ok(()); }
+
+        let mode = match (self.config.read().durability, point) {
+            (Durability::None, _) => None,
+            (Durability::FlushOnly, SyncPoint::Auto) => None,
+            (Durability::FlushOnly, _) => Some(SyncMode::Data),
+            (Durability::DataSync, _) => Some(SyncMode::Data),
+            (Durability::FullSync, _) => Some(SyncMode::All),
+            (Durability::SyncEveryN(_), SyncPoint::Shutdown) => Some(SyncMode::Data),
+            (Durability::SyncEveryN(n), _) if unsynced >= n as u64 => Some(SyncMode::Data),
+            (Durability::SyncEveryN(_), _) => None,
+        };
+
+        match mode {
+            Some(mode) => self.sync(mode).await,
+            None => Ok(()),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        let unsynced = self.unsynced.load(Ordering::Relaxed);
+        let timeout = self.config.read().write_timeout;
+        self.target.sync(mode).with_timeout(timeout).await?;
+        self.unsynced.fetch_sub(unsynced, Ordering::Relaxed);
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "write_at", skip_all, fields(
+        target_id = self.id, offset = offset, len = tracing::field::Empty, path = tracing::field::Empty,
+        queue_depth = tracing::field::Empty, outcome = tracing::field::Empty, error = tracing::field::Empty,
+    )))]
     pub async fn write_at(&self, offset: u64, data: impl Into<Bytes>) -> Result<()> {
         let bytes = data.into();
+        let (start, size) = (minstant::Instant::now(), bytes.len() as u64);
+        trace::record!("len", size);
+        let result = self.write(offset, bytes).await;
+        self.metrics.record_write(start, size, &result);
+        trace::finish(&result, || self.queue_depth());
+        result
+    }
+
+    async fn write(&self, offset: u64, bytes: Bytes) -> Result<()> {
+        let _writing = self.begin_write()?;
         let avg = self.metrics.avg_write_latency.load(Ordering::Relaxed);
         self.metrics.last_in.store(TIME_CACHE.get_cached(), Ordering::Relaxed);
 
-        if avg > self.threshold_ns || bytes.len() < 4 * 1024 {
-            let mut should_flush = false;
+        let (threshold_ns, small_write_cutoff, write_timeout) = {
+            let config = self.config.read();
+            (config.latency_threshold_ns, config.small_write_cutoff, config.write_timeout)
+        };
+
+        // Held until the direct write lands, so no flush can overtake it.
+        let shared = self.target_lock.read().await;
+        let end = offset + bytes.len() as u64;
 
-            {
-                let mut q = self.write_queue.write();
-                q.push(PendingWrite { offset, data: bytes });
-                if q.total_bytes > 16 * 1024 {
-                    should_flush = true;
+        // A write over still queued data is queued too, or the older data would land on top of it.
+        if avg > threshold_ns || bytes.len() < small_write_cutoff || self.overlaps_queued(offset, end) {
+            drop(shared);
+            trace::record!("path", "queued");
+            let size = bytes.len() as u64;
+            self.reserve(size).await?;
+            let op = PendingWrite { offset, data: bytes, seq: 0 };
+
+            let (should_flush, replaced) = match &self.wal {
+                Some(wal) => {
+                    let _gate = wal.gate.lock().await;
+                    if let Err(e) = wal.append(&op).await {
+                        self.release(size);
+                        return Err(e);
+                    }
+                    self.enqueue([op])
                 }
-            }
+                None => self.enqueue([op]),
+            };
+            self.release(replaced);
 
             if should_flush {
-                self.flush().await?;
+                self.flush_queue(SyncPoint::Auto).await?;
             }
         } else {
-            self.target.write_at(bytes, offset)
-                .with_timeout(self.write_timeout)
-                .measure_latency(&self.metrics.avg_write_latency)
-                .await?;
+            trace::record!("path", "direct");
+            self.preserve(&[(offset, bytes.len())]).await;
+            let retry = self.config.read().retry.clone();
+            let result = retry.run(|| {
+                self.target.write_at(bytes.clone(), offset)
+                    .with_timeout(write_timeout)
+                    .measure_latency(&self.metrics.avg_write_latency)
+            }).await;
+            // Even a failed write may have changed part of the range.
+            self.invalidate(offset, end);
+            drop(shared);
+            result?;
+            self.unsynced.fetch_add(1, Ordering::Relaxed);
+            self.sync_for(SyncPoint::Auto).await?;
         }
         Ok(())
     }
 
-    pub async fn read_at(self: Arc<Self>, offset: u64, len: u64) -> Result<Bytes> {
-        let read_end = offset + len;
+    /// Queues `writes` all at once: readers see either none or all of them, and with
+    /// a WAL they are logged as one record, so a crash cannot keep only some of them.
+    pub(crate) async fn commit(&self, writes: Vec<(u64, Bytes)>) -> Result<()> {
+        // The policy may have changed since the transaction started.
+        self.check_transactions()?;
+        if writes.is_empty() { return Ok(()); }
+        let start = minstant::Instant::now();
+        let size = writes.iter().map(|(_, data)| data.len() as u64).sum();
+        let result = self.queue_all(writes, size).await;
+        self.metrics.record_write(start, size, &result);
+        result
+    }
+
+    /// Fails if a durability policy promises crash safety that a transaction could not keep:
+    /// without a WAL, a crash during the flush can leave only some of its writes on the target.
+    pub(crate) fn check_transactions(&self) -> Result<()> {
+        if self.wal.is_none() && self.config.read().durability != Durability::None {
+            return Err(TargetError::TransactionNeedsWal(self.id).into());
+        }
+        Ok(())
+    }
+
+    async fn queue_all(&self, writes: Vec<(u64, Bytes)>, size: u64) -> Result<()> {
+        let _writing = self.begin_write()?;
+        self.metrics.last_in.store(TIME_CACHE.get_cached(), Ordering::Relaxed);
+
+        self.reserve(size).await?;
+        let ops: Vec<PendingWrite> = writes.into_iter()
+            .map(|(offset, data)| PendingWrite { offset, data, seq: 0 })
+            .collect();
 
-        let find_exact_in_q = |q: &WriteQueue| {
-            q.writes.iter().rev().find(|p| {
-                p.offset == offset && p.data.len() as u64 == len
-            }).map(|p| p.data.clone())
+        let (should_flush, replaced) = match &self.wal {
+            Some(wal) => {
+                let _gate = wal.gate.lock().await;
+                if let Err(e) = wal.append_all(&ops).await {
+                    self.release(size);
+                    return Err(e);
+                }
+                self.enqueue(ops)
+            }
+            None => self.enqueue(ops),
         };
+        self.release(replaced);
 
-        {
-            let w_guard = self.write_queue.read();
-            if let Some(data) = find_exact_in_q(&w_guard) { return Ok(data); }
-            
-            let f_guard = self.flushing_queue.read();
-            if let Some(data) = find_exact_in_q(&f_guard) { return Ok(data); }
+        if should_flush {
+            self.flush_queue(SyncPoint::Auto).await?;
+        }
+        Ok(())
+    }
+
+    /// Takes the flush lock for a cross-target batch, writing out the queue first if `drain`.
+    pub(crate) async fn prepare_batch(&self, drain: bool) -> Result<OwnedMutexGuard<()>> {
+        let guard = Arc::clone(&self.flush_lock).lock_owned().await;
+        if drain {
+            self.drain_queue().await?;
+        }
+        Ok(guard)
+    }
+
+    /// Writes this target's share of a batch through to it and syncs, whatever the
+    /// durability policy. The guard from `prepare_batch` must be held.
+    ///
+    /// The writes are queued with the lowest sequence number, so anything already queued
+    /// stays on top. They are not logged to the WAL, the registry journal covers them.
+    pub(crate) async fn apply_batch(&self, writes: Vec<(u64, Bytes)>) -> Result<()> {
+        let _writing = self.begin_write()?;
+        self.metrics.last_in.store(TIME_CACHE.get_cached(), Ordering::Relaxed);
+
+        // The batch is already committed, so it cannot wait for room in the budget.
+        let size = writes.iter().map(|(_, data)| data.len() as u64).sum();
+        self.metrics.queued_bytes.fetch_add(size, Ordering::AcqRel);
+        if let Some(budget) = &self.budget {
+            budget.force_acquire(size);
         }
+        let replaced = {
+            let mut q = self.write_queue.write();
+            writes.into_iter().map(|(offset, data)| q.push(PendingWrite { offset, data, seq: 0 })).sum()
+        };
+        self.release(replaced);
+
+        self.drain_queue().await?;
+        let mode = match self.config.read().durability {
+            Durability::FullSync => SyncMode::All,
+            _ => SyncMode::Data,
+        };
+        self.sync(mode).await
+    }
+
+    /// Length of the target once every queued write has landed.
+    pub async fn len(&self) -> Result<u64> {
+        // Read before the target, so data moving from the queues to the target is seen in one of them.
+        let queued = self.write_queue.read().end().max(self.flushing_queue.read().end());
+        let read_timeout = self.config.read().read_timeout;
+        let len = self.target.len().with_timeout(read_timeout).await?;
+        Ok(len.max(queued))
+    }
+
+    /// Truncates or zero-extends the target to `len` bytes.
+    ///
+    /// Writes queued before the call keep their bytes below `len` and lose the rest,
+    /// as if they had reached the target first. Writes made after it are not affected.
+    pub async fn set_len(&self, len: u64) -> Result<()> {
+        let _writing = self.begin_write()?;
+        let _guard = self.flush_lock.lock().await;
+        let exclusive = self.target_lock.write().await;
+
+        let dropped = self.write_queue.write().truncate(len);
+        self.release(dropped);
+
+        // Otherwise recovery would replay the dropped bytes past the new end.
+        if let Some(wal) = &self.wal {
+            let _gate = wal.gate.lock().await;
+            let remaining: Vec<PendingWrite> = self.write_queue.read().iter().collect();
+            wal.reset(&remaining).await?;
+        }
+
+        let (read_timeout, write_timeout) = {
+            let config = self.config.read();
+            (config.read_timeout, config.write_timeout)
+        };
+        if let Ok(old) = self.target.len().with_timeout(read_timeout).await
+            && old > len {
+            self.preserve(&[(len, (old - len) as usize)]).await;
+        }
+
+        self.readahead.clear();
+        let result = self.target.set_len(len).with_timeout(write_timeout).await;
+        self.invalidate(len, u64::MAX);
+        drop(exclusive);
+        result?;
+
+        self.unsynced.fetch_add(1, Ordering::Relaxed);
+        self.sync_for(SyncPoint::Auto).await
+    }
+
+    /// Registers a snapshot of the target and the queues as they are now.
+    pub(crate) async fn snapshot(&self) -> Arc<SnapshotState> {
+        // Nothing reaches the target while this is held, so it and the queues are seen at one moment.
+        let _exclusive = self.target_lock.write().await;
+        let (read_timeout, limit) = {
+            let config = self.config.read();
+            (config.read_timeout, config.snapshot_limit)
+        };
+        let queued = self.pending_in(0, u64::MAX);
+        let len = self.target.len().with_timeout(read_timeout).await.ok()
+            .map(|len| len.max(queued.end()));
+
+        let state = Arc::new(SnapshotState::new(len, queued, limit, Arc::clone(&self.metrics)));
+        let mut snapshots = self.snapshots.lock();
+        snapshots.retain(|snapshot| snapshot.strong_count() > 0);
+        snapshots.push(Arc::downgrade(&state));
+        state
+    }
+
+    /// Copies what the target holds in `ranges` into every live snapshot, before it is
+    /// overwritten there. Snapshots expire if the old contents cannot be read.
+    async fn preserve(&self, ranges: &[(u64, usize)]) {
+        let snapshots: Vec<Arc<SnapshotState>> = self.snapshots.lock().iter()
+            .filter_map(Weak::upgrade)
+            .filter(|snapshot| !snapshot.is_expired())
+            .collect();
+        if snapshots.is_empty() { return; }
+
+        // Nothing past the longest snapshot needs saving, which spares appends a read.
+        let limit = snapshots.iter().map(|snapshot| snapshot.len().unwrap_or(u64::MAX)).max().unwrap_or(0);
+        let ranges: Vec<(u64, usize)> = ranges.iter()
+            .filter(|(offset, _)| *offset < limit)
+            .map(|(offset, len)| (*offset, (*len as u64).min(limit - offset) as usize))
+            .collect();
+        if ranges.is_empty() { return; }
+
+        let read_timeout = self.config.read().read_timeout;
+        match self.target.read_vectored_at(&ranges).with_timeout(read_timeout).await {
+            Ok(current) => for ((offset, len), data) in ranges.iter().zip(current) {
+                let data = pad(data, *len);
+                for snapshot in &snapshots {
+                    snapshot.preserve(*offset, &data);
+                }
+            },
+            Err(_) => for snapshot in &snapshots {
+                snapshot.expire();
+            },
+        }
+    }
+
+    pub fn report(&self) -> MetricsReport {
+        MetricsReport::new(std::any::type_name::<T>(), &self.metrics, self.queue_depth())
+    }
+
+    /// Segments in the write queue or being flushed
+    pub fn queue_depth(&self) -> u64 {
+        (self.write_queue.read().len() + self.flushing_queue.read().len()) as u64
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Stops taking writes, waits for the ones under way and writes the queue out with a sync.
+    /// Writes fail with `Error::Closed` from then on; reads and flushes still work.
+    pub async fn close(&self) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        // Frees budget first, in case a write under way is waiting for room.
+        self.flush_queue(SyncPoint::Auto).await?;
+        loop {
+            let done = self.writers_done.notified();
+            if self.writers.load(Ordering::SeqCst) == 0 { break; }
+            done.await;
+        }
+        self.flush_queue(SyncPoint::Shutdown).await?;
+
+        // Whatever the policy, a closed target is left synced.
+        if self.unsynced.load(Ordering::Relaxed) > 0 {
+            self.sync(SyncMode::Data).await?;
+        }
+        Ok(())
+    }
+
+    /// Counts a write as under way, unless the context is closed.
+    fn begin_write(&self) -> Result<WriteGuard<'_>> {
+        self.writers.fetch_add(1, Ordering::SeqCst);
+        let guard = WriteGuard { writers: &self.writers, done: &self.writers_done };
+        if self.is_closed() {
+            return Err(Error::Closed);
+        }
+        Ok(guard)
+    }
+
+    /// Waits until `bytes` more can be queued without going over the per-target
+    /// or registry-wide budget, flushing this target first if that may help.
+    async fn reserve(&self, bytes: u64) -> Result<()> {
+        let (limit, mode, write_timeout) = {
+            let config = self.config.read();
+            (config.memory_budget, config.backpressure, config.write_timeout)
+        };
+        if limit.is_none() && self.budget.is_none() {
+            self.metrics.queued_bytes.fetch_add(bytes, Ordering::AcqRel);
+            return Ok(());
+        }
+
+        let deadline = tokio::time::Instant::now() + write_timeout;
+        let mut flushed = false;
+
+        loop {
+            // Registered before checking, so a release in between is not missed.
+            let local = self.capacity.notified();
+            tokio::pin!(local);
+            local.as_mut().enable();
+            let mut global = self.budget.as_ref().map(|budget| Box::pin(budget.released.notified()));
+            if let Some(global) = global.as_mut() {
+                global.as_mut().enable();
+            }
+
+            if self.try_reserve(bytes, limit) { return Ok(()); }
+            if mode == BackpressureMode::FailFast { return Err(Error::QueueFull); }
+
+            if !flushed && !self.write_queue.read().is_empty() {
+                flushed = true;
+                self.flush_queue(SyncPoint::Auto).await?;
+                continue;
+            }
 
-        let mut potential_patches = Vec::new();
-        let collect_patches = |q: &WriteQueue, target: &mut Vec<PendingWrite>| {
-            for p in &q.writes {
-                let p_end = p.offset + p.data.len() as u64;
-                if p.offset < read_end && p_end > offset {
-                    target.push(p.clone());
+            let global = async {
+                match global.as_mut() {
+                    Some(global) => global.as_mut().await,
+                    None => std::future::pending().await,
                 }
+            };
+            tokio::select! {
+                _ = &mut local => {}
+                _ = global => {}
+                _ = tokio::time::sleep_until(deadline) => return Err(Error::Timeout),
+            }
+        }
+    }
+
+    fn try_reserve(&self, bytes: u64, limit: Option<u64>) -> bool {
+        let queued = &self.metrics.queued_bytes;
+        let reserved = queued.fetch_update(Ordering::AcqRel, Ordering::Acquire, |q| match limit {
+            Some(limit) if q > 0 && q + bytes > limit => None,
+            _ => Some(q + bytes),
+        }).is_ok();
+        if !reserved { return false; }
+
+        if let Some(budget) = &self.budget
+            && !budget.try_acquire(bytes) {
+            queued.fetch_sub(bytes, Ordering::AcqRel);
+            return false;
+        }
+        true
+    }
+
+    fn release(&self, bytes: u64) {
+        if bytes == 0 { return; }
+
+        self.metrics.queued_bytes.fetch_sub(bytes, Ordering::AcqRel);
+        self.capacity.notify_waiters();
+        if let Some(budget) = &self.budget {
+            budget.release(bytes);
+        }
+    }
+
+    fn overlaps_queued(&self, offset: u64, end: u64) -> bool {
+        self.write_queue.read().overlapping(offset, end).next().is_some()
+            || self.flushing_queue.read().overlapping(offset, end).next().is_some()
+    }
+
+    /// Queues `ops` in order under one lock, returning whether the queue should be
+    /// flushed and how many queued bytes the writes replaced.
+    fn enqueue(&self, ops: impl IntoIterator<Item = PendingWrite>) -> (bool, u64) {
+        let mut q = self.write_queue.write();
+        let mut replaced = 0;
+        for mut op in ops {
+            op.seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+            replaced += q.push(op);
+        }
+
+        let config = self.config.read();
+        (q.total_bytes() > config.max_queue_bytes || q.len() >= config.max_queue_ops, replaced)
+    }
+
+    /// Queued bytes within `[offset, end)`, newest first where the queues overlap.
+    fn pending_in(&self, offset: u64, end: u64) -> IntervalMap {
+        let mut pending = IntervalMap::new();
+        let w_guard = self.write_queue.read();
+        let f_guard = self.flushing_queue.read();
+
+        for op in f_guard.overlapping(offset, end).chain(w_guard.overlapping(offset, end)) {
+            let start = op.offset.max(offset);
+            let stop = (op.offset + op.data.len() as u64).min(end);
+            let data = op.data.slice((start - op.offset) as usize..(stop - op.offset) as usize);
+            pending.insert(start, data, op.seq);
+        }
+        pending
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "read_at", skip_all, fields(
+        target_id = self.id, offset = offset, len = len, source = tracing::field::Empty,
+        queue_depth = tracing::field::Empty, outcome = tracing::field::Empty, error = tracing::field::Empty,
+    )))]
+    pub async fn read_at(self: Arc<Self>, offset: u64, len: u64) -> Result<Bytes> {
+        let start = minstant::Instant::now();
+        let ctx = Arc::clone(&self);
+        let result = self.read(offset, len).await;
+        ctx.metrics.record_read(start, len, &result);
+        trace::finish(&result, || ctx.queue_depth());
+        result
+    }
+
+    async fn read(self: Arc<Self>, offset: u64, len: u64) -> Result<Bytes> {
+        let read_end = offset + len;
+        self.schedule_readahead(offset, len);
+
+        // Queued data answers on its own when it covers the whole range.
+        let pending = self.pending_in(offset, read_end);
+        if pending.total_bytes() == len {
+            trace::record!("source", "queue");
+            return Ok(patch(BytesMut::zeroed(len as usize), offset, &pending));
+        }
+
+        // Nothing leaves the queues while the flush lock is held, so collecting
+        // after the target read also picks up writes queued during it.
+        let _guard = self.flush_lock.lock().await;
+
+        let epoch = self.write_epoch.load(Ordering::Acquire);
+        let disk_data = match self.readahead.lookup(offset, len, epoch) {
+            Some(data) => {
+                trace::record!("source", "readahead");
+                self.metrics.readahead_hits.fetch_add(1, Ordering::Relaxed);
+                data
+            }
+            None => {
+                trace::record!("source", "target");
+                self.read_target(offset, len).await?
             }
         };
-        {
-            let f_guard = self.flushing_queue.read();
-            collect_patches(&f_guard, &mut potential_patches);
-            
-            let w_guard = self.write_queue.read();
-            collect_patches(&w_guard, &mut potential_patches);
+
+        // With nothing queued over the range, the target's bytes go out as they are.
+        let pending = self.pending_in(offset, read_end);
+        if pending.is_empty() && disk_data.len() as u64 == len {
+            return Ok(disk_data);
         }
 
+        let mut buf = BytesMut::from(&disk_data[..]);
+        buf.resize(len as usize, 0);
+        Ok(patch(buf, offset, &pending))
+    }
+
+    /// Reads every `(offset, len)` range with queued writes applied. Ranges the queues
+    /// do not cover are fetched with one vectored read, or block by block through the cache.
+    pub async fn read_vectored_at(&self, ranges: &[(u64, u64)]) -> Result<Vec<Bytes>> {
+        let start = minstant::Instant::now();
+        let result = self.read_vectored(ranges).await;
+        self.metrics.record_read(start, ranges.iter().map(|(_, len)| len).sum(), &result);
+        result
+    }
+
+    async fn read_vectored(&self, ranges: &[(u64, u64)]) -> Result<Vec<Bytes>> {
         let _guard = self.flush_lock.lock().await;
 
-        potential_patches.clear();
-        {
-            let f_guard = self.flushing_queue.read();
-            collect_patches(&f_guard, &mut potential_patches);
-            
-            let w_guard = self.write_queue.read();
-            collect_patches(&w_guard, &mut potential_patches);
+        let covered: Vec<bool> = ranges.iter()
+            .map(|(offset, len)| self.pending_in(*offset, offset + len).total_bytes() == *len)
+            .collect();
+        let missing: Vec<(u64, usize)> = ranges.iter().zip(&covered)
+            .filter(|(_, covered)| !**covered)
+            .map(|((offset, len), _)| (*offset, *len as usize))
+            .collect();
+
+        let (read_timeout, use_cache) = {
+            let config = self.config.read();
+            (config.read_timeout, config.block_cache && self.cache.is_some())
+        };
+        let fetched = if missing.is_empty() {
+            Vec::new()
+        } else if use_cache {
+            let mut fetched = Vec::with_capacity(missing.len());
+            for (offset, len) in &missing {
+                fetched.push(self.read_target(*offset, *len as u64).await?);
+            }
+            fetched
+        } else {
+            self.target.read_vectored_at(&missing)
+                .with_timeout(read_timeout)
+                .measure_latency(&self.metrics.avg_read_latency)
+                .await?
+        };
+
+        let mut fetched = fetched.into_iter();
+        Ok(ranges.iter().zip(covered).map(|((offset, len), covered)| {
+            let data = if covered { Bytes::new() } else { fetched.next().unwrap_or_default() };
+            let mut buf = BytesMut::from(&data[..]);
+            buf.resize(*len as usize, 0);
+            patch(buf, *offset, &self.pending_in(*offset, offset + len))
+        }).collect())
+    }
+
+    /// Reads from the target, through the block cache when it is enabled.
+    async fn read_target(&self, offset: u64, len: u64) -> Result<Bytes> {
+        let (read_timeout, use_cache) = {
+            let config = self.config.read();
+            (config.read_timeout, config.block_cache)
+        };
+        let cache = match &self.cache {
+            Some(cache) if use_cache && len > 0 => cache,
+            _ => {
+                return self.target.read_at(offset, len as usize)
+                    .with_timeout(read_timeout)
+                    .measure_latency(&self.metrics.avg_read_latency)
+                    .await;
+            }
+        };
+
+        let block_size = cache.block_size() as u64;
+        let first = offset / block_size;
+        let last = (offset + len - 1) / block_size;
+        let mut blocks: Vec<Option<Bytes>> = (first..=last).map(|b| cache.get(self.cache_owner, b)).collect();
+
+        let hits = blocks.iter().filter(|b| b.is_some()).count() as u64;
+        self.metrics.cache_hits.fetch_add(hits, Ordering::Relaxed);
+        self.metrics.cache_misses.fetch_add(blocks.len() as u64 - hits, Ordering::Relaxed);
+
+        // One read covers every missing block, along with any cached ones in between.
+        if let (Some(lo), Some(hi)) = (blocks.iter().position(Option::is_none), blocks.iter().rposition(Option::is_none)) {
+            let epoch = self.write_epoch.load(Ordering::Acquire);
+            let span = ((hi - lo + 1) as u64 * block_size) as usize;
+            let data = self.target.read_at((first + lo as u64) * block_size, span)
+                .with_timeout(read_timeout)
+                .measure_latency(&self.metrics.avg_read_latency)
+                .await?;
+
+            cache.fill(self.cache_owner, first + lo as u64, &data, || {
+                self.write_epoch.load(Ordering::Acquire) == epoch
+            });
+
+            let mut data = BytesMut::from(&data[..]);
+            data.resize(span, 0);
+            let data = data.freeze();
+            for (i, block) in blocks[lo..=hi].iter_mut().enumerate() {
+                let start = i * block_size as usize;
+                *block = Some(data.slice(start..start + block_size as usize));
+            }
         }
 
-        let disk_data = self.target.read_at(offset, len as usize)
-            .with_timeout(self.read_timeout)
-            .measure_latency(&self.metrics.avg_read_latency)
-            .await?;
+        let mut buf = BytesMut::with_capacity(blocks.len() * block_size as usize);
+        for block in blocks.into_iter().flatten() {
+            buf.extend_from_slice(&block);
+        }
+        let start = (offset - first * block_size) as usize;
+        Ok(buf.freeze().slice(start..start + len as usize))
+    }
 
-        {
-             let w_guard = self.write_queue.read();
-             collect_patches(&w_guard, &mut potential_patches);
+    /// Drops cached and prefetched data in `[offset, end)` once the target has been written there.
+    fn invalidate(&self, offset: u64, end: u64) {
+        self.write_epoch.fetch_add(1, Ordering::AcqRel);
+        if let Some(cache) = &self.cache {
+            cache.invalidate(self.cache_owner, offset, end);
         }
+    }
 
-        let mut buf = BytesMut::from(&disk_data[..]);
-        
-        for patch in potential_patches {
-            let p_start = patch.offset;
-            let p_end = patch.offset + patch.data.len() as u64;
-            if p_start >= read_end || p_end <= offset { continue; }
-
-            let start_in_buf = if p_start > offset { (p_start - offset) as usize } else { 0 };
-            let end_in_buf = if p_end < read_end { (p_end - offset) as usize } else { (read_end - offset) as usize };
-            
-            let start_in_patch = if p_start < offset { (offset - p_start) as usize } else { 0 };
-            
-            let len_to_copy = end_in_buf - start_in_buf;
-             
-            buf[start_in_buf..end_in_buf].copy_from_slice(
-                &patch.data[start_in_patch..start_in_patch + len_to_copy]
-            );
+    /// Starts prefetching past `[offset, offset + len)` if reads look sequential.
+    fn schedule_readahead(self: &Arc<Self>, offset: u64, len: u64) {
+        let max_window = self.config.read().readahead;
+        let Some(prefetch) = self.readahead.observe(offset, len, max_window) else { return };
+
+        let epoch = self.write_epoch.load(Ordering::Acquire);
+        let ctx = Arc::clone(self);
+        tokio::spawn(async move {
+            let data = ctx.read_target(prefetch.offset, prefetch.len).await.ok();
+            ctx.readahead.complete(prefetch, data, epoch);
+        });
+    }
+}
+
+/// A registered context seen without its target type, so that targets of different
+/// types can be managed together. Only [`IoContext`] implements it.
+#[async_trait]
+pub trait ErasedContext: sealed::Sealed + Send + Sync + 'static {
+    /// Id the target is registered under
+    fn id(&self) -> u64;
+
+    /// Registry-wide number of this registration of the id
+    fn generation(&self) -> u64;
+
+    /// Type name of the target
+    fn target_type(&self) -> &'static str;
+
+    fn metrics(&self) -> Arc<IoMetrics>;
+
+    fn report(&self) -> MetricsReport;
+
+    /// Segments in the write queue or being flushed
+    fn queue_depth(&self) -> u64;
+
+    /// Writes the queue out to the target, like `BufferWriter::flush`.
+    async fn flush(&self) -> Result<()>;
+
+    /// Stops taking writes and writes the queue out with a sync, see [`IoContext::close`].
+    async fn close(&self) -> Result<()>;
+
+    fn is_closed(&self) -> bool;
+
+    /// The context itself, for downcasting to `IoContext<T>`.
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+
+    /// Flush that syncs only as the durability policy asks of automatic ones.
+    #[doc(hidden)]
+    async fn flush_auto(&self) -> Result<()>;
+
+    /// See `IoContext::prepare_batch`
+    #[doc(hidden)]
+    async fn prepare_batch(&self, drain: bool) -> Result<OwnedMutexGuard<()>>;
+
+    /// See `IoContext::apply_batch`
+    #[doc(hidden)]
+    async fn apply_batch(&self, writes: Vec<(u64, Bytes)>) -> Result<()>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl<T: IoTarget> sealed::Sealed for IoContext<T> {}
+
+#[async_trait]
+impl<T: IoTarget> ErasedContext for IoContext<T> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn target_type(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+
+    fn metrics(&self) -> Arc<IoMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    fn report(&self) -> MetricsReport {
+        IoContext::report(self)
+    }
+
+    fn queue_depth(&self) -> u64 {
+        IoContext::queue_depth(self)
+    }
+
+    async fn flush(&self) -> Result<()> {
+        IoContext::flush(self).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        IoContext::close(self).await
+    }
+
+    fn is_closed(&self) -> bool {
+        IoContext::is_closed(self)
+    }
+
+    fn as_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    async fn flush_auto(&self) -> Result<()> {
+        self.flush_queue(SyncPoint::Auto).await
+    }
+
+    async fn prepare_batch(&self, drain: bool) -> Result<OwnedMutexGuard<()>> {
+        IoContext::prepare_batch(self, drain).await
+    }
+
+    async fn apply_batch(&self, writes: Vec<(u64, Bytes)>) -> Result<()> {
+        IoContext::apply_batch(self, writes).await
+    }
+}
+
+impl<T: IoTarget> Drop for IoContext<T> {
+    fn drop(&mut self) {
+        if let Some(cache) = &self.cache {
+            cache.purge(self.cache_owner);
         }
+    }
+}
 
-        Ok(buf.freeze())
+/// `data` cut or zero-padded to exactly `len` bytes.
+fn pad(data: Bytes, len: usize) -> Bytes {
+    if data.len() >= len {
+        return data.slice(..len);
     }
-}
\ No newline at end of file
+    let mut buf = BytesMut::from(&data[..]);
+    buf.resize(len, 0);
+    buf.freeze()
+}
+
+/// Adjacent `(offset, data)` pairs as one buffer.
+fn join(writes: &[(u64, Bytes)]) -> Bytes {
+    if let [(_, data)] = writes {
+        return data.clone();
+    }
+    let mut buf = BytesMut::with_capacity(writes.iter().map(|(_, data)| data.len()).sum());
+    for (_, data) in writes {
+        buf.extend_from_slice(data);
+    }
+    buf.freeze()
+}
+
+/// Copies `pending` over `buf`, which holds the bytes starting at `offset`.
+fn patch(mut buf: BytesMut, offset: u64, pending: &IntervalMap) -> Bytes {
+    for (start, seg) in pending.iter() {
+        let start = (start - offset) as usize;
+        buf[start..start + seg.data.len()].copy_from_slice(&seg.data);
+    }
+    buf.freeze()
+}
@@ -0,0 +1,212 @@
+use std::{future::Future, io::{self, SeekFrom}, pin::Pin, sync::Arc, task::{Context, Poll, ready}};
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use crate::{BufferReader, BufferWriter, IoTarget, ctx::SyncPoint};
+
+type IoFuture<T> = Pin<Box<dyn Future<Output = ringest_error::Result<T>> + Send>>;
+
+/// Position `pos` points at, from `current` in a stream of `len` bytes.
+fn seek_to(current: u64, len: u64, pos: SeekFrom) -> io::Result<u64> {
+    let position = match pos {
+        SeekFrom::Start(n) => Some(n),
+        SeekFrom::Current(n) => current.checked_add_signed(n),
+        SeekFrom::End(n) => len.checked_add_signed(n),
+    };
+    position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative or overflowing position"))
+}
+
+/// [`AsyncRead`] and [`AsyncSeek`] over a target, starting at offset 0.
+///
+/// Reads go through the buffered context, so they see writes still in the queue.
+/// The stream ends at the target's current length, looked up on every read and on
+/// every seek from the end, so it follows writes made after the cursor was created.
+pub struct ReadCursor<T: IoTarget> {
+    reader: BufferReader<T>,
+    position: u64,
+    read: Option<IoFuture<Bytes>>,
+    /// Length lookup for a seek from the end, and the offset from there
+    seek: Option<(IoFuture<u64>, i64)>,
+}
+
+impl<T: IoTarget> ReadCursor<T> {
+    pub fn new(reader: BufferReader<T>) -> Self {
+        Self {
+            reader,
+            position: 0,
+            read: None,
+            seek: None,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn into_inner(self) -> BufferReader<T> {
+        self.reader
+    }
+}
+
+impl<T: IoTarget> AsyncRead for ReadCursor<T> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let read = match &mut this.read {
+            Some(read) => read,
+            None => {
+                if buf.remaining() == 0 { return Poll::Ready(Ok(())); }
+                let ctx = Arc::clone(&this.reader.context);
+                let (position, wanted) = (this.position, buf.remaining() as u64);
+                this.read.insert(Box::pin(async move {
+                    let len = wanted.min(ctx.len().await?.saturating_sub(position));
+                    if len == 0 { return Ok(Bytes::new()); }
+                    ctx.read_at(position, len).await
+                }))
+            }
+        };
+
+        let result = ready!(read.as_mut().poll(cx));
+        this.read = None;
+        let data = result?;
+
+        // The buffer may have shrunk since the read started.
+        let n = data.len().min(buf.remaining());
+        buf.put_slice(&data[..n]);
+        this.position += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: IoTarget> AsyncSeek for ReadCursor<T> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.seek.is_some() {
+            return Err(io::Error::other("a seek is in flight, call poll_complete before start_seek"));
+        }
+        // A read in flight has no side effects, so it is simply dropped.
+        this.read = None;
+        match pos {
+            SeekFrom::End(n) => {
+                let ctx = Arc::clone(&this.reader.context);
+                this.seek = Some((Box::pin(async move { ctx.len().await }), n));
+            }
+            pos => this.position = seek_to(this.position, 0, pos)?,
+        }
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        if let Some((len, n)) = this.seek.as_mut() {
+            let result = ready!(len.as_mut().poll(cx));
+            let n = *n;
+            this.seek = None;
+            this.position = seek_to(this.position, result?, SeekFrom::End(n))?;
+        }
+        Poll::Ready(Ok(this.position))
+    }
+}
+
+/// [`AsyncWrite`] and [`AsyncSeek`] over a target, starting at offset 0.
+///
+/// Writes go through the buffered context like [`BufferWriter::write_at`]; `poll_flush`
+/// flushes the queue and `poll_shutdown` does what [`BufferWriter::shutdown`] does.
+/// Seeking from the end counts writes that are still queued.
+pub struct WriteCursor<T: IoTarget> {
+    writer: BufferWriter<T>,
+    position: u64,
+    /// Write in flight and its length
+    write: Option<(IoFuture<()>, usize)>,
+    flush: Option<IoFuture<()>>,
+    /// Length lookup for a seek from the end, and the offset from there
+    seek: Option<(IoFuture<u64>, i64)>,
+}
+
+impl<T: IoTarget> WriteCursor<T> {
+    pub fn new(writer: BufferWriter<T>) -> Self {
+        Self {
+            writer,
+            position: 0,
+            write: None,
+            flush: None,
+            seek: None,
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn into_inner(self) -> BufferWriter<T> {
+        self.writer
+    }
+
+    /// Drives the write in flight, if any, to completion.
+    fn poll_written(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let Some((write, len)) = self.write.as_mut() else { return Poll::Ready(Ok(0)) };
+        let result = ready!(write.as_mut().poll(cx));
+        let len = *len;
+        self.write = None;
+        result?;
+        self.position += len as u64;
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flushed(&mut self, cx: &mut Context<'_>, point: SyncPoint) -> Poll<io::Result<()>> {
+        ready!(self.poll_written(cx))?;
+        let ctx = Arc::clone(&self.writer.context);
+        let flush = self.flush.get_or_insert_with(|| Box::pin(async move { ctx.flush_queue(point).await }));
+        let result = ready!(flush.as_mut().poll(cx));
+        self.flush = None;
+        Poll::Ready(result.map_err(Into::into))
+    }
+}
+
+impl<T: IoTarget> AsyncWrite for WriteCursor<T> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.write.is_none() {
+            if buf.is_empty() { return Poll::Ready(Ok(0)); }
+            let ctx = Arc::clone(&this.writer.context);
+            let (offset, data) = (this.position, Bytes::copy_from_slice(buf));
+            this.write = Some((Box::pin(async move { ctx.write_at(offset, data).await }), buf.len()));
+        }
+        this.poll_written(cx)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flushed(cx, SyncPoint::Flush)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flushed(cx, SyncPoint::Shutdown)
+    }
+}
+
+impl<T: IoTarget> AsyncSeek for WriteCursor<T> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.write.is_some() || this.seek.is_some() {
+            return Err(io::Error::other("an operation is in flight, call poll_complete before start_seek"));
+        }
+        match pos {
+            SeekFrom::End(n) => {
+                let ctx = Arc::clone(&this.writer.context);
+                this.seek = Some((Box::pin(async move { ctx.len().await }), n));
+            }
+            pos => this.position = seek_to(this.position, 0, pos)?,
+        }
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        ready!(this.poll_written(cx))?;
+        if let Some((len, n)) = this.seek.as_mut() {
+            let result = ready!(len.as_mut().poll(cx));
+            let n = *n;
+            this.seek = None;
+            this.position = seek_to(this.position, result?, SeekFrom::End(n))?;
+        }
+        Poll::Ready(Ok(this.position))
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, fmt::Write as _, sync::Arc};
+use ringest_error::Result;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}};
+use crate::{LatencySummary, MetricsReport, Registry};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+/// Longest request head the endpoint reads before giving up on a client
+const MAX_REQUEST: usize = 8 * 1024;
+
+type Counter = (&'static str, &'static str, fn(&MetricsReport) -> u64);
+type Gauge = (&'static str, &'static str, fn(&MetricsReport) -> f64);
+type Summary = (&'static str, &'static str, fn(&MetricsReport) -> LatencySummary);
+
+const COUNTERS: [Counter; 12] = [
+    ("ringest_ops", "Reads and writes through the context", |r| r.total_ops),
+    ("ringest_reads", "Reads through the context", |r| r.reads),
+    ("ringest_read_bytes", "Bytes read through the context", |r| r.read_bytes),
+    ("ringest_writes", "Writes through the context", |r| r.writes),
+    ("ringest_written_bytes", "Bytes written through the context", |r| r.written_bytes),
+    ("ringest_flushes", "Flushes that had something to write", |r| r.flushes),
+    ("ringest_flushed_bytes", "Queued bytes written out by flushes", |r| r.flushed_bytes),
+    ("ringest_timeouts", "Operations that timed out", |r| r.timeouts),
+    ("ringest_cache_hits", "Blocks served from the block cache", |r| r.cache_hits),
+    ("ringest_cache_misses", "Blocks read from the target for the block cache", |r| r.cache_misses),
+    ("ringest_readahead_hits", "Reads served from prefetched data", |r| r.readahead_hits),
+    ("ringest_expired_snapshots", "Snapshots that went over their memory limit", |r| r.expired_snapshots),
+];
+
+const GAUGES: [Gauge; 6] = [
+    ("ringest_queued_bytes", "Bytes in the write queue or being flushed", |r| r.queued_bytes as f64),
+    ("ringest_queued_writes", "Segments in the write queue or being flushed", |r| r.queued_writes as f64),
+    ("ringest_coalescing_ratio", "Queued segments per target write", |r| r.coalescing_ratio),
+    ("ringest_snapshot_bytes", "Bytes kept alive by snapshots", |r| r.snapshot_bytes as f64),
+    ("ringest_avg_read_latency_seconds", "Moving average of target read latency", |r| seconds(r.avg_read_latency_ns)),
+    ("ringest_avg_write_latency_seconds", "Moving average of direct target write latency", |r| seconds(r.avg_write_latency_ns)),
+];
+
+const SUMMARIES: [Summary; 3] = [
+    ("ringest_read_latency_seconds", "Latency of reads through the context", |r| r.read_latency),
+    ("ringest_write_latency_seconds", "Latency of writes through the context", |r| r.write_latency),
+    ("ringest_flush_latency_seconds", "Latency of flushes that had something to write", |r| r.flush_latency),
+];
+
+fn seconds(nanos: u64) -> f64 {
+    nanos as f64 / 1e9
+}
+
+/// Label value with `\`, `"` and newlines escaped.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Renders `reports` in the OpenMetrics text format, one sample per target in every family,
+/// labelled with `target_id` and `target_type`.
+pub fn render(reports: &BTreeMap<u64, MetricsReport>) -> String {
+    let labels: Vec<(String, &MetricsReport)> = reports.iter()
+        .map(|(id, report)| (format!("target_id=\"{id}\",target_type=\"{}\"", escape(report.target_type)), report))
+        .collect();
+    let mut out = String::new();
+
+    for (name, help, value) in COUNTERS {
+        let _ = writeln!(out, "# TYPE {name} counter\n# HELP {name} {help}.");
+        for (labels, report) in &labels {
+            let _ = writeln!(out, "{name}_total{{{labels}}} {}", value(report));
+        }
+    }
+    for (name, help, value) in GAUGES {
+        let _ = writeln!(out, "# TYPE {name} gauge\n# HELP {name} {help}.");
+        for (labels, report) in &labels {
+            let _ = writeln!(out, "{name}{{{labels}}} {}", value(report));
+        }
+    }
+    for (name, help, value) in SUMMARIES {
+        let _ = writeln!(out, "# TYPE {name} summary\n# HELP {name} {help}.");
+        for (labels, report) in &labels {
+            let summary = value(report);
+            for (quantile, nanos) in [("0.5", summary.p50_ns), ("0.99", summary.p99_ns), ("0.999", summary.p999_ns)] {
+                let _ = writeln!(out, "{name}{{{labels},quantile=\"{quantile}\"}} {}", seconds(nanos));
+            }
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", seconds(summary.sum_ns));
+            let _ = writeln!(out, "{name}_count{{{labels}}} {}", summary.count);
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+impl Registry {
+    /// Metrics of every registered target in the OpenMetrics text format, see [`render`].
+    pub fn render_metrics(&self) -> String {
+        render(&self.metrics_snapshot())
+    }
+
+    /// Answers `GET /metrics` on `listener` with [`Registry::render_metrics`] until accepting fails.
+    ///
+    /// Meant for a local scrape or a test, not as a general HTTP server: every connection
+    /// gets one response and is closed, and anything but `GET /metrics` gets a 404.
+    pub async fn serve_metrics(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let registry = Arc::clone(&self);
+            tokio::spawn(async move {
+                let _ = registry.respond(stream).await;
+            });
+        }
+    }
+
+    async fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut head = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 { break; }
+            head.extend_from_slice(&buf[..n]);
+        }
+
+        let request_line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+        let mut parts = request_line.split(|b| *b == b' ');
+        let response = match (parts.next(), parts.next()) {
+            (Some(b"GET"), Some(b"/metrics")) => {
+                let body = self.render_metrics();
+                format!("HTTP/1.1 200 OK\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
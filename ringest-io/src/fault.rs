@@ -0,0 +1,227 @@
+use std::{collections::HashMap, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::RwLock;
+use ringest_error::{Error, Result};
+use crate::{IoTarget, SyncMode};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail with an `Error::Io`
+    Fail,
+    /// Fail with `Error::Timeout`
+    Timeout,
+    /// Sleep, then run the operation normally
+    Delay(Duration),
+    /// Flip every bit of the data read or written
+    Corrupt,
+    /// Reads return only the first N bytes; writes persist only the first N bytes, then fail
+    Truncate(usize),
+}
+
+/// Which operations a [`FaultyTarget`] sabotages. Operation numbers start at 1.
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    writes: HashMap<u64, Fault>,
+    reads: HashMap<u64, Fault>,
+    every_write: Option<Fault>,
+    every_read: Option<Fault>,
+}
+
+impl FaultPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_write(mut self, nth: u64, fault: Fault) -> Self {
+        self.writes.insert(nth, fault);
+        self
+    }
+
+    pub fn on_read(mut self, nth: u64, fault: Fault) -> Self {
+        self.reads.insert(nth, fault);
+        self
+    }
+
+    /// Applies to every write without a fault of its own
+    pub fn every_write(mut self, fault: Fault) -> Self {
+        self.every_write = Some(fault);
+        self
+    }
+
+    /// Applies to every read without a fault of its own
+    pub fn every_read(mut self, fault: Fault) -> Self {
+        self.every_read = Some(fault);
+        self
+    }
+
+    fn write_fault(&self, nth: u64) -> Option<Fault> {
+        self.writes.get(&nth).or(self.every_write.as_ref()).cloned()
+    }
+
+    fn read_fault(&self, nth: u64) -> Option<Fault> {
+        self.reads.get(&nth).or(self.every_read.as_ref()).cloned()
+    }
+}
+
+/// Wraps an [`IoTarget`] and injects failures according to a [`FaultPlan`].
+pub struct FaultyTarget<T: IoTarget> {
+    inner: T,
+    plan: RwLock<FaultPlan>,
+    writes: AtomicU64,
+    reads: AtomicU64,
+}
+
+impl<T: IoTarget> FaultyTarget<T> {
+    pub fn new(inner: T, plan: FaultPlan) -> Self {
+        Self {
+            inner,
+            plan: RwLock::new(plan),
+            writes: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Replaces the plan. Operation numbering continues from where it was.
+    pub fn set_plan(&self, plan: FaultPlan) {
+        *self.plan.write() = plan;
+    }
+
+    /// Number of writes attempted so far, failed ones included
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// Number of reads attempted so far, failed ones included
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+fn injected(op: &str) -> Error {
+    Error::Io(std::io::Error::other(format!("injected {} fault", op)))
+}
+
+fn corrupt(data: &[u8]) -> Bytes {
+    data.iter().map(|b| !b).collect::<Vec<u8>>().into()
+}
+
+#[async_trait]
+impl<T: IoTarget> IoTarget for FaultyTarget<T> {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        let nth = self.reads.fetch_add(1, Ordering::Relaxed) + 1;
+        let fault = self.plan.read().read_fault(nth);
+
+        match fault {
+            None => self.inner.read_at(offset, len).await,
+            Some(Fault::Fail) => Err(injected("read")),
+            Some(Fault::Timeout) => Err(Error::Timeout),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.read_at(offset, len).await
+            }
+            Some(Fault::Corrupt) => {
+                let data = self.inner.read_at(offset, len).await?;
+                Ok(corrupt(&data))
+            }
+            Some(Fault::Truncate(n)) => {
+                let data = self.inner.read_at(offset, len).await?;
+                Ok(data.slice(..n.min(data.len())))
+            }
+        }
+    }
+
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        let nth = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        let fault = self.plan.read().write_fault(nth);
+
+        match fault {
+            None => self.inner.write_at(content, offset).await,
+            Some(Fault::Fail) => Err(injected("write")),
+            Some(Fault::Timeout) => Err(Error::Timeout),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.write_at(content, offset).await
+            }
+            Some(Fault::Corrupt) => self.inner.write_at(corrupt(&content), offset).await,
+            Some(Fault::Truncate(n)) => {
+                self.inner.write_at(content.slice(..n.min(content.len())), offset).await?;
+                Err(injected("write"))
+            }
+        }
+    }
+
+    /// Counts as one write, and a fault applies to all the pairs together.
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        let nth = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        let fault = self.plan.read().write_fault(nth);
+
+        match fault {
+            None => self.inner.write_vectored_at(writes).await,
+            Some(Fault::Fail) => Err(injected("write")),
+            Some(Fault::Timeout) => Err(Error::Timeout),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.write_vectored_at(writes).await
+            }
+            Some(Fault::Corrupt) => {
+                let corrupted: Vec<_> = writes.iter().map(|(offset, data)| (*offset, corrupt(data))).collect();
+                self.inner.write_vectored_at(&corrupted).await
+            }
+            Some(Fault::Truncate(n)) => {
+                self.inner.write_vectored_at(&truncate(writes, n)).await?;
+                Err(injected("write"))
+            }
+        }
+    }
+
+    /// Counts as one read, and a fault applies to all the ranges together.
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        let nth = self.reads.fetch_add(1, Ordering::Relaxed) + 1;
+        let fault = self.plan.read().read_fault(nth);
+
+        match fault {
+            None => self.inner.read_vectored_at(ranges).await,
+            Some(Fault::Fail) => Err(injected("read")),
+            Some(Fault::Timeout) => Err(Error::Timeout),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.read_vectored_at(ranges).await
+            }
+            Some(Fault::Corrupt) => {
+                let data = self.inner.read_vectored_at(ranges).await?;
+                Ok(data.iter().map(|data| corrupt(data)).collect())
+            }
+            Some(Fault::Truncate(n)) => {
+                let data = self.inner.read_vectored_at(ranges).await?;
+                let pairs: Vec<_> = data.into_iter().map(|data| (0, data)).collect();
+                Ok(truncate(&pairs, n).into_iter().map(|(_, data)| data).collect())
+            }
+        }
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        self.inner.sync(mode).await
+    }
+
+    async fn len(&self) -> Result<u64> {
+        self.inner.len().await
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        self.inner.set_len(len).await
+    }
+}
+
+/// Keeps the first `n` bytes of `pairs` across all of them; the pairs past that come back empty.
+fn truncate(pairs: &[(u64, Bytes)], mut n: usize) -> Vec<(u64, Bytes)> {
+    pairs.iter().map(|(offset, data)| {
+        let len = data.len().min(n);
+        n -= len;
+        (*offset, data.slice(..len))
+    }).collect()
+}
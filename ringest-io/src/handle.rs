@@ -0,0 +1,52 @@
+use std::{fmt, marker::PhantomData};
+
+/// Typed reference to a target, returned by [`Registry::insert`](crate::Registry::insert).
+///
+/// It names one registration of the id: once the target is removed and the id registered
+/// again, lookups through the old handle fail with `TargetError::Stale` rather than reaching
+/// the new target.
+pub struct TargetHandle<T> {
+    id: u64,
+    generation: u64,
+    _target: PhantomData<fn() -> T>,
+}
+
+impl<T> TargetHandle<T> {
+    pub(crate) fn new(id: u64, generation: u64) -> Self {
+        Self { id, generation, _target: PhantomData }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Registry-wide number of the registration this handle came from
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl<T> Clone for TargetHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TargetHandle<T> {}
+
+impl<T> PartialEq for TargetHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.id, self.generation) == (other.id, other.generation)
+    }
+}
+
+impl<T> Eq for TargetHandle<T> {}
+
+impl<T> fmt::Debug for TargetHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TargetHandle")
+            .field("id", &self.id)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
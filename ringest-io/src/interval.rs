@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use bytes::Bytes;
+use crate::PendingWrite;
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub data: Bytes,
+    pub seq: u64,
+}
+
+impl Segment {
+    fn end(&self, start: u64) -> u64 {
+        start + self.data.len() as u64
+    }
+}
+
+/// Non-overlapping byte ranges keyed by offset.
+///
+/// Inserting a range keeps, byte for byte, whichever write has the higher sequence
+/// number, so the map always holds the newest data for every offset it covers.
+#[derive(Debug, Clone, Default)]
+pub struct IntervalMap {
+    segments: BTreeMap<u64, Segment>,
+    total_bytes: u64,
+}
+
+impl IntervalMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Bytes covered by the map
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.total_bytes = 0;
+    }
+
+    /// End of the last segment, or 0 when empty
+    pub fn end(&self) -> u64 {
+        self.segments.last_key_value().map_or(0, |(start, seg)| seg.end(*start))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &Segment)> {
+        self.segments.iter().map(|(start, seg)| (*start, seg))
+    }
+
+    /// Segments that intersect `[offset, end)`, in offset order.
+    pub fn overlapping(&self, offset: u64, end: u64) -> impl Iterator<Item = (u64, &Segment)> {
+        // Only the last segment starting at or before `offset` can reach into the range from the left.
+        let from = match self.segments.range(..=offset).next_back() {
+            Some((start, seg)) if seg.end(*start) > offset => *start,
+            _ => offset,
+        };
+        self.segments.range(from..end.max(from)).map(|(start, seg)| (*start, seg))
+    }
+
+    pub fn insert(&mut self, offset: u64, data: Bytes, seq: u64) {
+        let end = offset + data.len() as u64;
+        if offset == end { return; }
+
+        // Parts of [offset, end) not already held by a newer write.
+        let mut pieces = Vec::new();
+        let mut cursor = offset;
+        for (start, seg) in self.overlapping(offset, end) {
+            if seg.seq <= seq { continue; }
+            if start > cursor {
+                pieces.push((cursor, start));
+            }
+            cursor = cursor.max(seg.end(start));
+        }
+        if cursor < end {
+            pieces.push((cursor, end));
+        }
+
+        for (start, stop) in pieces {
+            self.carve(start, stop);
+            let data = data.slice((start - offset) as usize..(stop - offset) as usize);
+            self.total_bytes += data.len() as u64;
+            self.segments.insert(start, Segment { data, seq });
+        }
+    }
+
+    /// Removes every byte in `[start, end)`, trimming segments that stick out of it.
+    pub fn carve(&mut self, start: u64, end: u64) {
+        let hit: Vec<u64> = self.overlapping(start, end).map(|(s, _)| s).collect();
+
+        for s in hit {
+            let seg = self.segments.remove(&s).expect("segment listed by overlapping()");
+            let e = seg.end(s);
+            self.total_bytes -= seg.data.len() as u64;
+
+            if s < start {
+                let head = seg.data.slice(..(start - s) as usize);
+                self.total_bytes += head.len() as u64;
+                self.segments.insert(s, Segment { data: head, seq: seg.seq });
+            }
+            if e > end {
+                let tail = seg.data.slice((end - s) as usize..);
+                self.total_bytes += tail.len() as u64;
+                self.segments.insert(end, Segment { data: tail, seq: seg.seq });
+            }
+        }
+    }
+}
+
+/// Segments a flush sends to the target as a single write.
+pub(crate) struct Run {
+    pub(crate) offset: u64,
+    pub(crate) end: u64,
+    pub(crate) segments: Vec<PendingWrite>,
+}
+
+impl Run {
+    /// Queued bytes in the run, not counting gaps
+    pub(crate) fn queued_bytes(&self) -> u64 {
+        self.segments.iter().map(|op| op.data.len() as u64).sum()
+    }
+}
+
+impl Run {
+    /// Queued segments as `(offset, data)` pairs, for a vectored write
+    pub(crate) fn writes(&self) -> Vec<(u64, Bytes)> {
+        self.segments.iter().map(|op| (op.offset, op.data.clone())).collect()
+    }
+
+    /// Holes between the segments as `(offset, len)` ranges
+    pub(crate) fn gaps(&self) -> Vec<(u64, usize)> {
+        self.segments.windows(2)
+            .map(|pair| (pair[0].offset + pair[0].data.len() as u64, pair[1].offset))
+            .filter(|(start, end)| end > start)
+            .map(|(start, end)| (start, (end - start) as usize))
+            .collect()
+    }
+
+    /// Splits the run at its gaps.
+    pub(crate) fn split(self) -> Vec<Run> {
+        group(self.segments, 0)
+    }
+}
+
+impl IntervalMap {
+    /// Groups segments separated by at most `max_gap` bytes into runs.
+    pub(crate) fn into_runs(self, max_gap: u64) -> Vec<Run> {
+        let ops = self.segments.into_iter()
+            .map(|(offset, seg)| PendingWrite { offset, data: seg.data, seq: seg.seq });
+        group(ops, max_gap)
+    }
+}
+
+fn group(ops: impl IntoIterator<Item = PendingWrite>, max_gap: u64) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+
+    for op in ops {
+        let end = op.offset + op.data.len() as u64;
+        match runs.last_mut() {
+            Some(run) if op.offset - run.end <= max_gap => {
+                run.end = end;
+                run.segments.push(op);
+            }
+            _ => runs.push(Run { offset: op.offset, end, segments: vec![op] }),
+        }
+    }
+    runs
+}
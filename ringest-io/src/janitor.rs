@@ -0,0 +1,87 @@
+use std::{sync::{Arc, Weak, atomic::{AtomicU64, Ordering}}, time::Duration};
+use ringest_error::{Error, Result};
+use tokio::{sync::Notify, task::{JoinHandle, JoinSet}};
+use crate::{ErasedContext, Registry, TIME_CACHE, trace};
+
+/// Handle to a janitor started by [`Registry::start_janitor`].
+///
+/// Dropping the handle leaves the janitor running until the registry is dropped.
+pub struct JanitorHandle {
+    stop: Arc<Notify>,
+    started: Arc<AtomicU64>,
+    task: JoinHandle<()>,
+}
+
+impl JanitorHandle {
+    /// Stops the janitor and waits until it and the flushes it started have finished.
+    pub async fn stop(self) -> Result<()> {
+        self.stop.notify_one();
+        self.task.await.map_err(|e| Error::Internal(e.to_string()))
+    }
+
+    /// Whether the janitor has ended, either stopped or because the registry is gone
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Flushes the janitor has started so far
+    pub fn flushes_started(&self) -> u64 {
+        self.started.load(Ordering::Relaxed)
+    }
+}
+
+impl Registry {
+    /// Starts a task that checks every target each `interval` and flushes those whose
+    /// queue has held writes for longer than `threshold_ms`, whatever their type.
+    ///
+    /// The janitor does not keep the registry alive; it ends once the registry is dropped.
+    pub fn start_janitor(self: &Arc<Self>, threshold_ms: u64, interval: Duration) -> JanitorHandle {
+        let stop = Arc::new(Notify::new());
+        let started = Arc::new(AtomicU64::new(0));
+        let task = tokio::spawn(run(Arc::downgrade(self), Arc::clone(&stop), Arc::clone(&started), threshold_ms, interval));
+        JanitorHandle { stop, started, task }
+    }
+}
+
+async fn run(registry: Weak<Registry>, stop: Arc<Notify>, started: Arc<AtomicU64>, threshold_ms: u64, interval: Duration) {
+    let mut timer = tokio::time::interval(interval);
+    let mut flushes = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = stop.notified() => break,
+        }
+        let Some(registry) = registry.upgrade() else { break };
+        let targets: Vec<Arc<dyn ErasedContext>> = registry.targets.iter().map(|entry| entry.value().clone()).collect();
+        drop(registry);
+
+        while flushes.try_join_next().is_some() {}
+        sweep(targets, threshold_ms, &started, &mut flushes);
+    }
+    while flushes.join_next().await.is_some() {}
+}
+
+/// Starts a flush of every target in `targets` whose queue holds writes and that has taken
+/// none for longer than `threshold_ms`, counting them in `started`.
+fn sweep(targets: Vec<Arc<dyn ErasedContext>>, threshold_ms: u64, started: &AtomicU64, flushes: &mut JoinSet<()>) {
+    #[cfg(feature = "tracing")]
+    let _sweep = tracing::debug_span!("janitor", targets = targets.len()).entered();
+    let now = TIME_CACHE.get_cached();
+
+    for ctx in targets {
+        let metrics = ctx.metrics();
+        let last_in = metrics.last_in.load(Ordering::Relaxed);
+
+        // Direct writes move `last_in` too, but leave nothing for a flush to do.
+        if ctx.queue_depth() > 0 && now.saturating_sub(last_in) > threshold_ms {
+            trace::event!(target_id = ctx.id(), idle_ms = now - last_in, queue_depth = ctx.queue_depth(), "flushing idle target");
+            let flush = async move {
+                let _ = ctx.flush_auto().await;
+            };
+            #[cfg(feature = "tracing")]
+            let flush = tracing::Instrument::in_current_span(flush);
+            started.fetch_add(1, Ordering::Relaxed);
+            flushes.spawn(flush);
+        }
+    }
+}
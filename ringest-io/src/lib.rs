@@ -3,17 +3,40 @@ pub mod read;
 pub mod write;
 pub mod ctx;
 pub mod time;
-
-use bytes::{BufMut, Bytes, BytesMut};
+pub mod wal;
+pub mod config;
+pub mod memory;
+pub mod fault;
+pub mod retry;
+pub mod budget;
+pub mod interval;
+pub mod cache;
+pub mod readahead;
+pub mod mmap;
+pub mod cursor;
+pub mod transaction;
+pub mod batch;
+pub mod snapshot;
+pub mod metrics;
+pub mod janitor;
+pub mod shutdown;
+pub mod handle;
+#[cfg(feature = "metrics-export")]
+pub mod export;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub mod uring;
+mod trace;
+
+use bytes::Bytes;
 use dashmap::DashMap;
 use async_trait::async_trait;
 use parking_lot::RwLock;
-use ringest_error::{Result, Error};
+use ringest_error::{Result, Error, TargetError};
 use tokio::sync::{Mutex, Notify};
 use std::sync::LazyLock;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::{any::Any, sync::Arc};
+use std::time::Duration;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 #[cfg(unix)]
 use std::os::unix::fs::FileExt;
@@ -25,7 +48,29 @@ use crate::read::PendingRead;
 use crate::time::TimeCache;
 pub use crate::write::BufferWriter;
 use crate::write::PendingWrite;
+pub use crate::ctx::ErasedContext;
 use crate::ctx::IoContext;
+use crate::wal::WriteAheadLog;
+use crate::interval::{IntervalMap, Run};
+use crate::readahead::Readahead;
+use crate::batch::Journal;
+pub use crate::config::{ConfigHandle, IoConfig, IoConfigBuilder};
+pub use crate::memory::MemoryTarget;
+pub use crate::fault::{Fault, FaultPlan, FaultyTarget};
+pub use crate::retry::{DeadLetter, DeadLetterHandler, RetryPolicy};
+pub use crate::budget::{BackpressureMode, MemoryBudget};
+pub use crate::cache::BlockCache;
+pub use crate::mmap::MmapTarget;
+pub use crate::cursor::{ReadCursor, WriteCursor};
+pub use crate::transaction::Transaction;
+pub use crate::batch::Batch;
+pub use crate::snapshot::Snapshot;
+pub use crate::metrics::{Histogram, IoMetrics, LatencySummary, MetricsReport};
+pub use crate::janitor::JanitorHandle;
+pub use crate::shutdown::ShutdownReport;
+pub use crate::handle::TargetHandle;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use crate::uring::UringTarget;
 
 pub(crate) static TIME_CACHE: LazyLock<TimeCache> = LazyLock::new(|| TimeCache::new(Duration::from_millis(5)));
 
@@ -43,143 +88,535 @@ where
         tokio::time::timeout(duration, self)
             .await
             .map_err(|_| Error::Timeout)?
-            .map_err(Into::into)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// File data and the metadata needed to read it back (`fdatasync`)
+    Data,
+    /// File data and all metadata (`fsync`)
+    All,
+}
+
+/// When a target is synced after its queued writes reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Never sync; flushed data may stay in the OS page cache
+    #[default]
+    None,
+    /// Sync data only on an explicit `flush` or `shutdown`, not on automatic flushes
+    FlushOnly,
+    /// Sync data after every flush and direct write
+    DataSync,
+    /// Sync data and metadata after every flush and direct write
+    FullSync,
+    /// Sync data once every N flushes or direct writes, and on `shutdown`
+    SyncEveryN(u32),
+}
+
 #[async_trait]
 pub trait IoTarget: Send + Sync + 'static {
     async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes>;
     async fn write_at(&self, content: Bytes, offset: u64) -> Result<()>;
+
+    async fn sync(&self, _mode: SyncMode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Current length in bytes
+    async fn len(&self) -> Result<u64> {
+        Err(unsupported("len"))
+    }
+
+    async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Truncates or zero-extends the target to `len` bytes.
+    async fn set_len(&self, _len: u64) -> Result<()> {
+        Err(unsupported("set_len"))
+    }
+
+    /// Writes every `(offset, data)` pair. Pairs that follow each other on the target
+    /// can go out as one operation, like `pwritev`; the default writes them one by one.
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        for (offset, data) in writes {
+            self.write_at(data.clone(), *offset).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads every `(offset, len)` range, zero-padded past the end, like `preadv`
+    /// where the ranges follow each other. The default reads them one by one.
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        let mut results = Vec::with_capacity(ranges.len());
+        for (offset, len) in ranges {
+            results.push(self.read_at(*offset, *len).await?);
+        }
+        Ok(results)
+    }
+
+    /// Performs every vectored write in `batch` and reports each outcome separately, in order.
+    ///
+    /// Flushes hand all their runs to the target through this. Targets that can submit
+    /// several writes at once override it; the default writes them one after another.
+    async fn write_batch_at(&self, batch: &[Vec<(u64, Bytes)>]) -> Vec<Result<()>> {
+        let mut results = Vec::with_capacity(batch.len());
+        for writes in batch {
+            results.push(self.write_vectored_at(writes).await);
+        }
+        results
+    }
 }
 
+fn unsupported(op: &str) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("target does not support {}", op)))
+}
+
+/// Queued writes, indexed by offset.
+///
+/// Overlaps are resolved as writes are queued: the one with the higher `seq` keeps
+/// its bytes, so the queue never holds two versions of the same byte.
 #[derive(Default, Clone)]
 pub struct WriteQueue {
-    pub writes: Vec<PendingWrite>,
-    pub total_bytes: u64,
+    segments: IntervalMap,
 }
 
 impl WriteQueue {
     pub fn new() -> Self {
         Self {
-            writes: Vec::new(),
-            total_bytes: 0,
+            segments: IntervalMap::new(),
         }
     }
 
-    pub fn push(&mut self, op: PendingWrite) {
-        self.total_bytes += op.data.len() as u64;
-        self.writes.push(op);
+    /// Queues `op` and returns how many bytes left the queue because newer data covers them.
+    pub fn push(&mut self, op: PendingWrite) -> u64 {
+        let before = self.segments.total_bytes() + op.data.len() as u64;
+        self.segments.insert(op.offset, op.data, op.seq);
+        before - self.segments.total_bytes()
+    }
+
+    /// Queued writes that intersect `[offset, end)`, in offset order.
+    pub fn overlapping(&self, offset: u64, end: u64) -> impl Iterator<Item = PendingWrite> + '_ {
+        self.segments.overlapping(offset, end)
+            .map(|(offset, seg)| PendingWrite { offset, data: seg.data.clone(), seq: seg.seq })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PendingWrite> + '_ {
+        self.segments.iter()
+            .map(|(offset, seg)| PendingWrite { offset, data: seg.data.clone(), seq: seg.seq })
+    }
+
+    /// Drops every queued byte at or past `len` and returns how many there were.
+    pub fn truncate(&mut self, len: u64) -> u64 {
+        let before = self.segments.total_bytes();
+        self.segments.carve(len, u64::MAX);
+        before - self.segments.total_bytes()
+    }
+
+    /// End of the furthest queued write, or 0 when empty
+    pub fn end(&self) -> u64 {
+        self.segments.end()
+    }
+
+    pub(crate) fn into_runs(self, max_gap: u64) -> Vec<Run> {
+        self.segments.into_runs(max_gap)
     }
 
     pub fn clear(&mut self) {
-        self.writes.clear();
-        self.total_bytes = 0;
+        self.segments.clear();
+    }
+
+    /// Bytes waiting in the queue
+    pub fn total_bytes(&self) -> u64 {
+        self.segments.total_bytes()
     }
 
     pub fn len(&self) -> usize {
-        self.writes.len()
+        self.segments.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.writes.is_empty()
+        self.segments.is_empty()
     }
 }
 
-pub struct IoMetrics {
-    pub avg_write_latency: AtomicU64,
-    pub avg_read_latency: AtomicU64,
-    pub total_ops: AtomicU64,
-    /// Last write to buffer
-    pub last_in: AtomicU64,
-    /// Last flush to target
-    pub last_out: AtomicU64,
+pub struct Registry {
+    targets: DashMap<u64, Arc<dyn ErasedContext>>,
+    /// Generation of the next registration; tells registrations of the same id apart
+    generations: AtomicU64,
+    budget: Option<Arc<MemoryBudget>>,
+    cache: Option<Arc<BlockCache>>,
+    journal_path: Option<PathBuf>,
+    /// Opened on first use
+    journal: parking_lot::Mutex<Option<Arc<Journal>>>,
+}
+
+pub struct RegistryBuilder {
+    memory_budget: Option<u64>,
+    block_cache: Option<u64>,
+    block_size: usize,
+    journal: Option<PathBuf>,
 }
 
-impl IoMetrics {
+impl Default for RegistryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegistryBuilder {
     pub fn new() -> Self {
         Self {
-            avg_read_latency: AtomicU64::new(0),
-            avg_write_latency: AtomicU64::new(0),
-            total_ops: AtomicU64::new(0),
-            last_in: AtomicU64::new(0),
-            last_out: AtomicU64::new(0),
+            memory_budget: None,
+            block_cache: None,
+            block_size: cache::DEFAULT_BLOCK_SIZE,
+            journal: None,
+        }
+    }
+
+    /// Budget of queued bytes shared by all targets
+    pub fn memory_budget(mut self, limit: u64) -> Self {
+        self.memory_budget = Some(limit);
+        self
+    }
+
+    /// Block cache of `capacity` bytes shared by the targets that enable it in their config
+    pub fn block_cache(mut self, capacity: u64) -> Self {
+        self.block_cache = Some(capacity);
+        self
+    }
+
+    /// Size of a cached block; 4 KiB by default
+    pub fn block_size(mut self, bytes: usize) -> Self {
+        self.block_size = bytes;
+        self
+    }
+
+    /// Journal file for [`Batch`] commits. Only opened when a batch is first committed
+    /// or recovered.
+    pub fn journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> Registry {
+        Registry {
+            targets: DashMap::new(),
+            generations: AtomicU64::new(1),
+            budget: self.memory_budget.map(|limit| Arc::new(MemoryBudget::new(limit))),
+            cache: self.block_cache.map(|capacity| Arc::new(BlockCache::new(capacity, self.block_size))),
+            journal_path: self.journal,
+            journal: parking_lot::Mutex::new(None),
         }
     }
 }
 
-pub struct Registry {
-    targets: DashMap<u64, Arc<dyn Any + Send + Sync>>,
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Registry {
     pub fn new() -> Self {
-        Self { targets: DashMap::new() }
+        Self::builder().build()
+    }
+
+    pub fn builder() -> RegistryBuilder {
+        RegistryBuilder::new()
+    }
+
+    /// Registry whose targets share a budget of `limit` queued bytes.
+    pub fn with_memory_budget(limit: u64) -> Self {
+        Self::builder().memory_budget(limit).build()
+    }
+
+    pub fn memory_budget(&self) -> Option<&MemoryBudget> {
+        self.budget.as_deref()
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.cache.as_deref()
+    }
+
+    /// Registers `target` under `id`, failing if the id is taken.
+    pub fn insert<T: IoTarget>(&self, id: u64, target: T, write_timeout: Duration, read_timeout: Duration) -> Result<TargetHandle<T>> {
+        let config = IoConfig::builder()
+            .write_timeout(write_timeout)
+            .read_timeout(read_timeout)
+            .build();
+        self.insert_with(id, target, config)
+    }
+
+    /// Registers `target` under `id` with its own [`IoConfig`], failing if the id is taken.
+    ///
+    /// If the config enables a write-ahead log that already holds records (the previous
+    /// owner of `id` crashed before flushing), they are queued ahead of any new write and
+    /// reach the target on the next flush.
+    pub fn insert_with<T: IoTarget>(&self, id: u64, target: T, config: IoConfig) -> Result<TargetHandle<T>> {
+        // Held until the context is in place, so the WAL of a registered target is never reopened.
+        let dashmap::Entry::Vacant(slot) = self.targets.entry(id) else {
+            return Err(TargetError::AlreadyRegistered(id).into());
+        };
+
+        let mut queue = WriteQueue::new();
+        let wal = match &config.wal_path {
+            Some(path) => {
+                let (wal, recovered) = WriteAheadLog::open(path)?;
+                for op in recovered {
+                    queue.push(op);
+                }
+                Some(Arc::new(wal))
+            }
+            None => None,
+        };
+
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        slot.insert(self.context(id, generation, target, config, wal, queue));
+        Ok(TargetHandle::new(id, generation))
+    }
+
+    pub fn config<T: IoTarget>(&self, id: u64) -> Result<ConfigHandle> {
+        Ok(self.lookup::<T>(id, None)?.config.clone())
+    }
+
+    /// Context of target `id`, whatever its type.
+    pub fn target(&self, id: u64) -> Option<Arc<dyn ErasedContext>> {
+        self.targets.get(&id).map(|entry| entry.value().clone())
     }
 
-    pub fn insert<T: IoTarget>(&self, id: u64, target: T, write_timeout: Duration, read_timeout: Duration) {
-        let ctx = Arc::new(IoContext {
+    /// Handle to target `id`, if it is registered with target type `T`.
+    pub fn handle<T: IoTarget>(&self, id: u64) -> Result<TargetHandle<T>> {
+        let ctx = self.lookup::<T>(id, None)?;
+        Ok(TargetHandle::new(id, ctx.generation))
+    }
+
+    /// Context of target `id` as an `IoContext<T>`, or why there is none.
+    /// With a `generation`, only that registration of the id will do.
+    fn lookup<T: IoTarget>(&self, id: u64, generation: Option<u64>) -> Result<Arc<IoContext<T>>> {
+        let ctx = self.target(id).ok_or(TargetError::NotFound(id))?;
+        if generation.is_some_and(|generation| generation != ctx.generation()) {
+            return Err(TargetError::Stale(id).into());
+        }
+        let actual = ctx.target_type();
+        ctx.as_any().downcast::<IoContext<T>>().map_err(|_| {
+            TargetError::WrongType { id, expected: std::any::type_name::<T>(), actual }.into()
+        })
+    }
+
+    fn context<T: IoTarget>(
+        &self,
+        id: u64,
+        generation: u64,
+        target: T,
+        config: IoConfig,
+        wal: Option<Arc<WriteAheadLog>>,
+        queue: WriteQueue,
+    ) -> Arc<IoContext<T>> {
+        // Recovered writes are already queued, so they are charged without waiting.
+        let next_seq = queue.iter().map(|op| op.seq).max().unwrap_or(0) + 1;
+        let metrics = IoMetrics::new();
+        metrics.queued_bytes.store(queue.total_bytes(), Ordering::Relaxed);
+        if let Some(budget) = &self.budget {
+            budget.force_acquire(queue.total_bytes());
+        }
+
+        Arc::new(IoContext {
+            id,
+            generation,
             target: Arc::new(target),
-            metrics: Arc::new(IoMetrics::new()),
-            write_queue: Arc::new(RwLock::new(WriteQueue::new())),
+            metrics: Arc::new(metrics),
+            write_queue: Arc::new(RwLock::new(queue)),
             read_queue: Arc::new(RwLock::new(Vec::new())),
             flushing_queue: Arc::new(RwLock::new(WriteQueue::new())),
-            write_timeout,
-            read_timeout,
-            threshold_ns: 1_000_000,
+            config: ConfigHandle::new(config),
             flush_lock: Arc::new(Mutex::new(())),
-        });
-        self.targets.insert(id, ctx);
+            target_lock: tokio::sync::RwLock::new(()),
+            next_seq: AtomicU64::new(next_seq),
+            wal,
+            unsynced: AtomicU64::new(0),
+            budget: self.budget.clone(),
+            capacity: Notify::new(),
+            cache_owner: self.cache.as_ref().map_or(0, |cache| cache.register()),
+            cache: self.cache.clone(),
+            write_epoch: AtomicU64::new(0),
+            readahead: Readahead::default(),
+            snapshots: parking_lot::Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+            writers: AtomicU64::new(0),
+            writers_done: Notify::new(),
+        })
+    }
+
+    /// Closes target `id`, writing out its queue, and unregisters it. If the queue cannot
+    /// be written out, the target stays registered (but closed) and the error is returned.
+    pub async fn remove(&self, id: u64) -> Result<()> {
+        let ctx = self.target(id).ok_or(TargetError::NotFound(id))?;
+        ctx.close().await?;
+        self.targets.remove_if(&id, |_, registered| Arc::ptr_eq(registered, &ctx));
+        Ok(())
+    }
+
+    pub fn get_writer<T: IoTarget>(&self, id: u64) -> Result<BufferWriter<T>> {
+        Ok(BufferWriter::new(self.lookup(id, None)?))
     }
 
-    pub fn remove(&self, id: u64) -> Result<()> {
-        if let Some(_) = self.targets.iter().find(|t| *t.key() == id) {
-            self.targets.remove(&id);
-            return Ok(())
+    pub fn get_reader<T: IoTarget>(&self, id: u64) -> Result<BufferReader<T>> {
+        Ok(BufferReader::new(self.lookup(id, None)?))
+    }
+
+    pub fn writer<T: IoTarget>(&self, handle: &TargetHandle<T>) -> Result<BufferWriter<T>> {
+        Ok(BufferWriter::new(self.lookup(handle.id(), Some(handle.generation()))?))
+    }
+
+    pub fn reader<T: IoTarget>(&self, handle: &TargetHandle<T>) -> Result<BufferReader<T>> {
+        Ok(BufferReader::new(self.lookup(handle.id(), Some(handle.generation()))?))
+    }
+
+    /// Metrics of every registered target by id, ready to serialize.
+    pub fn metrics_snapshot(&self) -> BTreeMap<u64, MetricsReport> {
+        self.targets.iter().map(|entry| (*entry.key(), entry.value().report())).collect()
+    }
+
+    /// Starts a batch of writes to several targets, see [`Batch`].
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    /// Writes batches that a crash left in the journal to their targets.
+    ///
+    /// Call it once every target they touch is registered (with its WAL, if it has one)
+    /// and before writing to them. Committing a batch does the same first.
+    pub async fn recover_batches(&self) -> Result<()> {
+        let journal = self.journal()?;
+        let _gate = journal.gate.lock().await;
+        batch::replay(self, &journal).await
+    }
+
+    fn journal(&self) -> Result<Arc<Journal>> {
+        let Some(path) = &self.journal_path else {
+            return Err(Error::Internal("Registry has no journal for batches".to_string()))
+        };
+        let mut journal = self.journal.lock();
+        if let Some(journal) = journal.as_ref() {
+            return Ok(journal.clone());
         }
-        Err(Error::Internal("Target with given id not found".to_string()))
+        Ok(journal.insert(Arc::new(Journal::open(path)?)).clone())
     }
 
-    pub fn get_writer<T: IoTarget>(&self, id: u64) -> Option<BufferWriter<T>> {
-        let ctx = self.targets.get(&id)?;
-        let context = ctx.value().clone().downcast::<IoContext<T>>().ok()?;
-        Some(BufferWriter::new(context))
+    /// Contexts registered under `ids`, in the same order.
+    fn contexts<'a>(&self, ids: impl Iterator<Item = &'a u64>) -> Result<Vec<Arc<dyn ErasedContext>>> {
+        ids.map(|id| match self.targets.get(id) {
+            Some(entry) => Ok(entry.value().clone()),
+            None => Err(TargetError::NotFound(*id).into()),
+        }).collect()
     }
+}
 
-    pub fn get_reader<T: IoTarget>(&self, id: u64) -> Option<BufferReader<T>> {
-        let ctx = self.targets.get(&id)?;
-        let context = ctx.value().clone().downcast::<IoContext<T>>().ok()?;
-        Some(BufferReader::new(context))
+#[async_trait]
+impl<T: IoTarget> IoTarget for Arc<T> {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        (**self).read_at(offset, len).await
     }
 
-    pub fn start_janitor<T: IoTarget>(self: Arc<Self>, threshold_ms: u64, interval: Duration) {
-        tokio::spawn(async move {
-            let mut timer = tokio::time::interval(interval);
-            loop {
-                timer.tick().await;
-                let now = TIME_CACHE.get_cached();
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        (**self).write_at(content, offset).await
+    }
 
-                for entry in self.targets.iter() {
-                    if let Ok(ctx) = entry.value().clone().downcast::<IoContext<T>>() {
-                        let last_in = ctx.metrics.last_in.load(Ordering::Relaxed);
-                        let last_out = ctx.metrics.last_out.load(Ordering::Relaxed);
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        (**self).sync(mode).await
+    }
 
-                        if last_in > last_out && (now - last_in) > threshold_ms {
-                            let ctx_clone = Arc::clone(&ctx);
-                            tokio::spawn(async move {
-                                let _ = ctx_clone.flush().await;
-                            });
-                        }
-                    }
-                }
-            }
-        });
+    async fn len(&self) -> Result<u64> {
+        (**self).len().await
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        (**self).set_len(len).await
+    }
+
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        (**self).write_vectored_at(writes).await
+    }
+
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        (**self).read_vectored_at(ranges).await
+    }
+
+    async fn write_batch_at(&self, batch: &[Vec<(u64, Bytes)>]) -> Vec<Result<()>> {
+        (**self).write_batch_at(batch).await
     }
 }
 
 pub trait PositionalIo {
     fn read_at_pos(&self, offset: u64, len: usize) -> std::io::Result<Vec<u8>>;
     fn write_at_pos(&self, offset: u64, data: &[u8]) -> std::io::Result<()>;
+
+    fn read_vectored_at_pos(&self, ranges: &[(u64, usize)]) -> std::io::Result<Vec<Vec<u8>>> {
+        ranges.iter().map(|(offset, len)| self.read_at_pos(*offset, *len)).collect()
+    }
+
+    fn write_vectored_at_pos(&self, writes: &[(u64, Bytes)]) -> std::io::Result<()> {
+        writes.iter().try_for_each(|(offset, data)| self.write_at_pos(*offset, data))
+    }
+}
+
+/// Most buffers passed to one `preadv`/`pwritev` call
+#[cfg(target_os = "linux")]
+const IOV_MAX: usize = 1024;
+
+/// Writes all of `bufs` at `offset` with as few `pwritev` calls as possible.
+#[cfg(target_os = "linux")]
+fn pwritev_all(file: &std::fs::File, mut offset: u64, bufs: &[&[u8]]) -> std::io::Result<()> {
+    use std::{io::IoSlice, os::fd::AsRawFd};
+
+    let mut slices: Vec<IoSlice> = bufs.iter().map(|buf| IoSlice::new(buf)).collect();
+    let mut slices = &mut slices[..];
+    IoSlice::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        let count = slices.len().min(IOV_MAX);
+        // Safety: `IoSlice` is ABI compatible with `iovec` and the slices outlive the call.
+        let n = unsafe { libc::pwritev(file.as_raw_fd(), slices.as_ptr().cast(), count as libc::c_int, offset as libc::off_t) };
+        if n < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted { continue; }
+            return Err(e);
+        }
+        if n == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "pwritev wrote nothing"));
+        }
+        offset += n as u64;
+        IoSlice::advance_slices(&mut slices, n as usize);
+    }
+    Ok(())
+}
+
+/// Fills `bufs` from `offset` with `preadv`, leaving whatever lies past the end untouched.
+#[cfg(target_os = "linux")]
+fn preadv_all(file: &std::fs::File, mut offset: u64, bufs: &mut [Vec<u8>]) -> std::io::Result<()> {
+    use std::{io::IoSliceMut, os::fd::AsRawFd};
+
+    let mut slices: Vec<IoSliceMut> = bufs.iter_mut().map(|buf| IoSliceMut::new(buf)).collect();
+    let mut slices = &mut slices[..];
+    IoSliceMut::advance_slices(&mut slices, 0);
+
+    while !slices.is_empty() {
+        let count = slices.len().min(IOV_MAX);
+        // Safety: `IoSliceMut` is ABI compatible with `iovec` and the slices outlive the call.
+        let n = unsafe { libc::preadv(file.as_raw_fd(), slices.as_ptr().cast(), count as libc::c_int, offset as libc::off_t) };
+        if n < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted { continue; }
+            return Err(e);
+        }
+        if n == 0 { break; }
+        offset += n as u64;
+        IoSliceMut::advance_slices(&mut slices, n as usize);
+    }
+    Ok(())
 }
 
 impl PositionalIo for std::fs::File {
@@ -199,6 +636,26 @@ impl PositionalIo for std::fs::File {
         FileExt::seek_write(self, data, offset)?;
         Ok(())
     }
+
+    #[cfg(target_os = "linux")]
+    fn read_vectored_at_pos(&self, ranges: &[(u64, usize)]) -> std::io::Result<Vec<Vec<u8>>> {
+        let mut results = Vec::with_capacity(ranges.len());
+        for run in ranges.chunk_by(|a, b| a.0 + a.1 as u64 == b.0) {
+            let mut bufs: Vec<Vec<u8>> = run.iter().map(|(_, len)| vec![0u8; *len]).collect();
+            preadv_all(self, run[0].0, &mut bufs)?;
+            results.extend(bufs);
+        }
+        Ok(results)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_vectored_at_pos(&self, writes: &[(u64, Bytes)]) -> std::io::Result<()> {
+        for run in writes.chunk_by(|a, b| a.0 + a.1.len() as u64 == b.0) {
+            let bufs: Vec<&[u8]> = run.iter().map(|(_, data)| data.as_ref()).collect();
+            pwritev_all(self, run[0].0, &bufs)?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -206,9 +663,7 @@ impl IoTarget for std::fs::File {
     async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
         let file = self.try_clone()?;
 
-        let data = tokio::task::spawn_blocking(move || {
-            file.read_at_pos(offset, len)
-        }).await.map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Join error"))??;
+        let data = trace::blocking("read_at", move || file.read_at_pos(offset, len)).await?;
 
         Ok(Bytes::from(data))
     }
@@ -216,9 +671,54 @@ impl IoTarget for std::fs::File {
     async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
         let file = self.try_clone()?;
 
-        tokio::task::spawn_blocking(move || {
-            file.write_at_pos(offset, &content)
-        }).await.map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Join error"))??;
+        trace::blocking("write_at", move || file.write_at_pos(offset, &content)).await?;
+
+        Ok(())
+    }
+
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        let file = self.try_clone()?;
+        let writes = writes.to_vec();
+
+        trace::blocking("write_vectored_at", move || file.write_vectored_at_pos(&writes)).await?;
+
+        Ok(())
+    }
+
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        let file = self.try_clone()?;
+        let ranges = ranges.to_vec();
+
+        let data = trace::blocking("read_vectored_at", move || file.read_vectored_at_pos(&ranges)).await?;
+
+        Ok(data.into_iter().map(Bytes::from).collect())
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        let file = self.try_clone()?;
+
+        trace::blocking("sync", move || {
+            match mode {
+                SyncMode::Data => file.sync_data(),
+                SyncMode::All => file.sync_all(),
+            }
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<u64> {
+        let file = self.try_clone()?;
+
+        let len = trace::blocking("len", move || file.metadata().map(|meta| meta.len())).await?;
+
+        Ok(len)
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        let file = self.try_clone()?;
+
+        trace::blocking("set_len", move || file.set_len(len)).await?;
 
         Ok(())
     }
@@ -229,9 +729,7 @@ impl IoTarget for tokio::fs::File {
     async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
         let std_file = self.try_clone().await?.into_std().await;
 
-        let data = tokio::task::spawn_blocking(move || {
-            std_file.read_at_pos(offset, len)
-        }).await.map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Join error"))??;
+        let data = trace::blocking("read_at", move || std_file.read_at_pos(offset, len)).await?;
 
         Ok(Bytes::from(data))
     }
@@ -239,10 +737,43 @@ impl IoTarget for tokio::fs::File {
     async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
         let std_file = self.try_clone().await?.into_std().await;
 
-        tokio::task::spawn_blocking(move || {
-            std_file.write_at_pos(offset, &content)
-        }).await.map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Join error"))??;
+        trace::blocking("write_at", move || std_file.write_at_pos(offset, &content)).await?;
+
+        Ok(())
+    }
+
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        let std_file = self.try_clone().await?.into_std().await;
+        let writes = writes.to_vec();
+
+        trace::blocking("write_vectored_at", move || std_file.write_vectored_at_pos(&writes)).await?;
+
+        Ok(())
+    }
+
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        let std_file = self.try_clone().await?.into_std().await;
+        let ranges = ranges.to_vec();
+
+        let data = trace::blocking("read_vectored_at", move || std_file.read_vectored_at_pos(&ranges)).await?;
+
+        Ok(data.into_iter().map(Bytes::from).collect())
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        match mode {
+            SyncMode::Data => self.sync_data().await?,
+            SyncMode::All => self.sync_all().await?,
+        }
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<u64> {
+        Ok(self.metadata().await?.len())
+    }
 
+    async fn set_len(&self, len: u64) -> Result<()> {
+        tokio::fs::File::set_len(self, len).await?;
         Ok(())
     }
 }
@@ -266,7 +797,7 @@ where
     async fn measure_latency(self, metric: &AtomicU64) -> Self::Out {
         let start = minstant::Instant::now();
         let result = self.await;
-        let latency = start.elapsed().as_micros() as u64;
+        let latency = start.elapsed().as_nanos() as u64;
 
         metric.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
             if avg == 0 { Some(latency) }
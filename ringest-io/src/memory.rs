@@ -0,0 +1,86 @@
+use std::io;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use parking_lot::RwLock;
+use ringest_error::Result;
+use crate::IoTarget;
+
+/// In-memory [`IoTarget`] for tests and caches.
+///
+/// Behaves like a sparse file: writing past the end zero-fills the gap, and reading
+/// past the end returns zeros for the missing part, just like the `std::fs::File` impl.
+#[derive(Default)]
+pub struct MemoryTarget {
+    data: RwLock<BytesMut>,
+}
+
+impl MemoryTarget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_content(content: impl AsRef<[u8]>) -> Self {
+        Self {
+            data: RwLock::new(BytesMut::from(content.as_ref())),
+        }
+    }
+
+    /// Copy of the current contents
+    pub fn snapshot(&self) -> Bytes {
+        Bytes::copy_from_slice(&self.data.read())
+    }
+
+    /// Current length in bytes, without going through the async [`IoTarget::len`]
+    pub fn size(&self) -> usize {
+        self.data.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.read().is_empty()
+    }
+}
+
+#[async_trait]
+impl IoTarget for MemoryTarget {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        let (start, end) = range(offset, len)?;
+        let data = self.data.read();
+        let mut buf = BytesMut::zeroed(len);
+
+        if start < data.len() {
+            let end = end.min(data.len());
+            buf[..end - start].copy_from_slice(&data[start..end]);
+        }
+
+        Ok(buf.freeze())
+    }
+
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        let (start, end) = range(offset, content.len())?;
+        let mut data = self.data.write();
+
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        data[start..end].copy_from_slice(&content);
+
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<u64> {
+        Ok(self.data.read().len() as u64)
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        let (_, len) = range(len, 0)?;
+        self.data.write().resize(len, 0);
+        Ok(())
+    }
+}
+
+/// `offset..offset + len` as indices, or `InvalidInput` if it does not fit in memory.
+fn range(offset: u64, len: usize) -> Result<(usize, usize)> {
+    usize::try_from(offset).ok()
+        .and_then(|start| Some((start, start.checked_add(len)?)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "range past the addressable end").into())
+}
@@ -0,0 +1,272 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use ringest_error::{Error, Result};
+use serde::Serialize;
+
+/// Sub-buckets per power of two; a recorded value is off by at most 1/8
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Lock-free histogram of nanosecond latencies.
+///
+/// Values fall into log-linear buckets: eight per power of two, so quantiles are exact
+/// below 8 ns and within 12.5% above. Recording is a few relaxed atomic adds.
+pub struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, nanos: u64) {
+        self.buckets[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Smallest bucket bound that at least `q` of the recorded values fall under, 0 when empty.
+    pub fn quantile(&self, q: f64) -> u64 {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 { return 0; }
+
+        let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return upper_bound(i).min(self.max.load(Ordering::Relaxed));
+            }
+        }
+        self.max.load(Ordering::Relaxed)
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let (count, sum) = (self.count(), self.sum.load(Ordering::Relaxed));
+        LatencySummary {
+            count,
+            sum_ns: sum,
+            mean_ns: sum.checked_div(count).unwrap_or(0),
+            p50_ns: self.quantile(0.5),
+            p99_ns: self.quantile(0.99),
+            p999_ns: self.quantile(0.999),
+            max_ns: self.max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 { return value as usize; }
+    let shift = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+    let sub = (value >> shift) as usize & (SUB_BUCKETS - 1);
+    (shift as usize + 1) * SUB_BUCKETS + sub
+}
+
+/// Largest value that falls into bucket `i`.
+fn upper_bound(i: usize) -> u64 {
+    if i < SUB_BUCKETS { return i as u64; }
+    let shift = (i / SUB_BUCKETS - 1) as u32;
+    let lower = ((SUB_BUCKETS + i % SUB_BUCKETS) as u64) << shift;
+    lower + ((1u64 << shift) - 1)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub sum_ns: u64,
+    pub mean_ns: u64,
+    pub p50_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+pub struct IoMetrics {
+    /// Moving average of direct target writes, in nanoseconds
+    pub avg_write_latency: AtomicU64,
+    /// Moving average of target reads, in nanoseconds
+    pub avg_read_latency: AtomicU64,
+    /// Reads and writes through the context
+    pub total_ops: AtomicU64,
+    /// Last write to buffer
+    pub last_in: AtomicU64,
+    /// Last flush to target
+    pub last_out: AtomicU64,
+    /// Bytes waiting in the write queue or being flushed
+    pub queued_bytes: AtomicU64,
+    /// Blocks served from the block cache
+    pub cache_hits: AtomicU64,
+    /// Blocks that had to be read from the target
+    pub cache_misses: AtomicU64,
+    /// Reads served from prefetched data
+    pub readahead_hits: AtomicU64,
+    /// Bytes kept alive by snapshots of the target
+    pub snapshot_bytes: AtomicU64,
+    /// Snapshots that went over their limit and dropped their data
+    pub expired_snapshots: AtomicU64,
+    pub reads: AtomicU64,
+    pub read_bytes: AtomicU64,
+    pub read_latency: Histogram,
+    pub writes: AtomicU64,
+    pub written_bytes: AtomicU64,
+    pub write_latency: Histogram,
+    /// Flushes that had something to write
+    pub flushes: AtomicU64,
+    pub flushed_bytes: AtomicU64,
+    pub flush_latency: Histogram,
+    /// Queued segments that flushes wrote, and the target writes they took
+    pub flushed_segments: AtomicU64,
+    pub flushed_runs: AtomicU64,
+    /// Operations that failed with `Error::Timeout`
+    pub timeouts: AtomicU64,
+}
+
+impl Default for IoMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoMetrics {
+    pub fn new() -> Self {
+        Self {
+            avg_read_latency: AtomicU64::new(0),
+            avg_write_latency: AtomicU64::new(0),
+            total_ops: AtomicU64::new(0),
+            last_in: AtomicU64::new(0),
+            last_out: AtomicU64::new(0),
+            queued_bytes: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            readahead_hits: AtomicU64::new(0),
+            snapshot_bytes: AtomicU64::new(0),
+            expired_snapshots: AtomicU64::new(0),
+            reads: AtomicU64::new(0),
+            read_bytes: AtomicU64::new(0),
+            read_latency: Histogram::new(),
+            writes: AtomicU64::new(0),
+            written_bytes: AtomicU64::new(0),
+            write_latency: Histogram::new(),
+            flushes: AtomicU64::new(0),
+            flushed_bytes: AtomicU64::new(0),
+            flush_latency: Histogram::new(),
+            flushed_segments: AtomicU64::new(0),
+            flushed_runs: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+        }
+    }
+
+    /// Queued segments per target write; 1 when flushes merge nothing
+    pub fn coalescing_ratio(&self) -> f64 {
+        let runs = self.flushed_runs.load(Ordering::Relaxed);
+        if runs == 0 { return 1.0; }
+        self.flushed_segments.load(Ordering::Relaxed) as f64 / runs as f64
+    }
+
+    pub(crate) fn record_read<T>(&self, start: minstant::Instant, bytes: u64, result: &Result<T>) {
+        self.total_ops.fetch_add(1, Ordering::Relaxed);
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.record(&self.read_latency, &self.read_bytes, start, bytes, result);
+    }
+
+    pub(crate) fn record_write<T>(&self, start: minstant::Instant, bytes: u64, result: &Result<T>) {
+        self.total_ops.fetch_add(1, Ordering::Relaxed);
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.record(&self.write_latency, &self.written_bytes, start, bytes, result);
+    }
+
+    pub(crate) fn record_flush<T>(&self, start: minstant::Instant, bytes: u64, result: &Result<T>) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.record(&self.flush_latency, &self.flushed_bytes, start, bytes, result);
+    }
+
+    fn record<T>(&self, latency: &Histogram, counter: &AtomicU64, start: minstant::Instant, bytes: u64, result: &Result<T>) {
+        latency.record(start.elapsed().as_nanos() as u64);
+        match result {
+            Ok(_) => { counter.fetch_add(bytes, Ordering::Relaxed); }
+            Err(Error::Timeout) => { self.timeouts.fetch_add(1, Ordering::Relaxed); }
+            Err(_) => {}
+        }
+    }
+}
+
+/// Point-in-time metrics of one target, as returned by `Registry::metrics_snapshot`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsReport {
+    /// Type name of the target
+    pub target_type: &'static str,
+    pub total_ops: u64,
+    pub reads: u64,
+    pub read_bytes: u64,
+    pub read_latency: LatencySummary,
+    pub writes: u64,
+    pub written_bytes: u64,
+    pub write_latency: LatencySummary,
+    pub flushes: u64,
+    pub flushed_bytes: u64,
+    pub flush_latency: LatencySummary,
+    pub coalescing_ratio: f64,
+    pub timeouts: u64,
+    /// Bytes in the write queue or being flushed
+    pub queued_bytes: u64,
+    /// Segments in the write queue or being flushed
+    pub queued_writes: u64,
+    pub avg_read_latency_ns: u64,
+    pub avg_write_latency_ns: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub readahead_hits: u64,
+    pub snapshot_bytes: u64,
+    pub expired_snapshots: u64,
+}
+
+impl MetricsReport {
+    pub(crate) fn new(target_type: &'static str, metrics: &IoMetrics, queued_writes: u64) -> Self {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Self {
+            target_type,
+            total_ops: load(&metrics.total_ops),
+            reads: load(&metrics.reads),
+            read_bytes: load(&metrics.read_bytes),
+            read_latency: metrics.read_latency.summary(),
+            writes: load(&metrics.writes),
+            written_bytes: load(&metrics.written_bytes),
+            write_latency: metrics.write_latency.summary(),
+            flushes: load(&metrics.flushes),
+            flushed_bytes: load(&metrics.flushed_bytes),
+            flush_latency: metrics.flush_latency.summary(),
+            coalescing_ratio: metrics.coalescing_ratio(),
+            timeouts: load(&metrics.timeouts),
+            queued_bytes: load(&metrics.queued_bytes),
+            queued_writes,
+            avg_read_latency_ns: load(&metrics.avg_read_latency),
+            avg_write_latency_ns: load(&metrics.avg_write_latency),
+            cache_hits: load(&metrics.cache_hits),
+            cache_misses: load(&metrics.cache_misses),
+            readahead_hits: load(&metrics.readahead_hits),
+            snapshot_bytes: load(&metrics.snapshot_bytes),
+            expired_snapshots: load(&metrics.expired_snapshots),
+        }
+    }
+}
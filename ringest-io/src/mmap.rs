@@ -0,0 +1,171 @@
+use std::{fs::File, io, path::Path, sync::Arc};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use memmap2::{MmapOptions, MmapRaw};
+use parking_lot::RwLock;
+use ringest_error::Result;
+use crate::{IoTarget, SyncMode};
+
+/// Owner that keeps a read-only mapping alive for the `Bytes` pointing into it.
+struct View(Arc<MmapRaw>);
+
+impl AsRef<[u8]> for View {
+    fn as_ref(&self) -> &[u8] {
+        // Safety: only built over read-only mappings, which this process never writes to,
+        // and the mapping lives as long as `self`.
+        unsafe { std::slice::from_raw_parts(self.0.as_ptr(), self.0.len()) }
+    }
+}
+
+/// [`IoTarget`] over a memory-mapped file, for read-mostly data.
+///
+/// Writes are copied into the mapping and reach the disk on `sync`, which calls `msync`.
+/// A write past the end grows the file and maps it again. Reads copy out of the mapping
+/// under the same lock as writes, so no slice ever sees the memory change under it.
+///
+/// A target opened with [`MmapTarget::read_only`] takes no writes and instead returns
+/// `Bytes` that point into the mapping. The file must then not be changed by anyone else
+/// while such `Bytes` are alive, the usual contract of a shared mapping.
+pub struct MmapTarget {
+    file: Arc<File>,
+    map: RwLock<Arc<MmapRaw>>,
+    writable: bool,
+}
+
+impl MmapTarget {
+    /// Opens (or creates) `path` for reading and writing.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::options().read(true).write(true).create(true).truncate(false).open(path)?;
+        Self::new(file)
+    }
+
+    /// Maps `file`, which must be open for reading and writing.
+    pub fn new(file: File) -> Result<Self> {
+        let map = MmapRaw::map_raw(&file)?;
+        Ok(Self { file: Arc::new(file), map: RwLock::new(Arc::new(map)), writable: true })
+    }
+
+    /// Maps `file` read-only. Reads return `Bytes` into the mapping without a copy;
+    /// writes and `set_len` fail with `PermissionDenied`.
+    pub fn read_only(file: File) -> Result<Self> {
+        let map = MmapOptions::new().map_raw_read_only(&file)?;
+        Ok(Self { file: Arc::new(file), map: RwLock::new(Arc::new(map)), writable: false })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Bytes currently mapped
+    pub fn mapped_len(&self) -> u64 {
+        self.map.read().len() as u64
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Maps the file again if `end` lies past the mapping and the file has grown since.
+    fn mapping(&self, end: u64) -> Result<()> {
+        let mapped = self.map.read().len() as u64;
+        if end <= mapped || self.file.metadata()?.len() <= mapped {
+            return Ok(());
+        }
+        self.grow(&mut self.map.write(), 0)
+    }
+
+    /// Grows the file to at least `len` bytes and maps all of it.
+    fn grow(&self, map: &mut Arc<MmapRaw>, len: u64) -> Result<()> {
+        let file_len = self.file.metadata()?.len();
+        if file_len < len {
+            File::set_len(&self.file, len)?;
+        }
+        if (map.len() as u64) < file_len.max(len) {
+            *map = Arc::new(self.map_file()?);
+        }
+        Ok(())
+    }
+
+    fn map_file(&self) -> io::Result<MmapRaw> {
+        match self.writable {
+            true => MmapRaw::map_raw(&*self.file),
+            false => MmapOptions::new().map_raw_read_only(&*self.file),
+        }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.writable { return Ok(()); }
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "memory-mapped target is read-only").into())
+    }
+}
+
+#[async_trait]
+impl IoTarget for MmapTarget {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        let end = offset.checked_add(len as u64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "read past u64::MAX"))?;
+        self.mapping(end)?;
+        let map = self.map.read();
+        let mapped = map.len() as u64;
+
+        if !self.writable && end <= mapped {
+            let start = offset as usize;
+            return Ok(Bytes::from_owner(View(map.clone())).slice(start..start + len));
+        }
+
+        // Like the `std::fs::File` impl, a read past the end is padded with zeros.
+        let mut buf = BytesMut::zeroed(len);
+        if offset < mapped {
+            let n = (end.min(mapped) - offset) as usize;
+            // Safety: the range is within the mapping, and writes wait for the lock held here.
+            unsafe {
+                std::ptr::copy_nonoverlapping(map.as_ptr().add(offset as usize), buf.as_mut_ptr(), n);
+            }
+        }
+        Ok(buf.freeze())
+    }
+
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        self.check_writable()?;
+        let end = offset.checked_add(content.len() as u64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "write past u64::MAX"))?;
+        let mut map = self.map.write();
+        if (map.len() as u64) < end {
+            self.grow(&mut map, end)?;
+        }
+
+        // Safety: `end` is within the mapping, and no reader holds a slice of it. Reads
+        // only copy out of a writable mapping, so `content` never points into it.
+        unsafe {
+            std::ptr::copy_nonoverlapping(content.as_ptr(), map.as_mut_ptr().add(offset as usize), content.len());
+        }
+        Ok(())
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        let map = self.map.read().clone();
+        let file = self.file.clone();
+
+        tokio::task::spawn_blocking(move || {
+            map.flush()?;
+            match mode {
+                SyncMode::Data => file.sync_data(),
+                SyncMode::All => file.sync_all(),
+            }
+        }).await.map_err(|_| std::io::Error::other("Join error"))??;
+
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        self.check_writable()?;
+        let mut map = self.map.write();
+        File::set_len(&self.file, len)?;
+        *map = Arc::new(self.map_file()?);
+        Ok(())
+    }
+}
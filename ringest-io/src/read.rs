@@ -1,15 +1,16 @@
 use bytes::Bytes;
-use crate::{IoContext, IoTarget, IoTimeoutExt, LatencyMeasureExt, write};
-use std::sync::{Arc, atomic::Ordering};
+use crate::{IoContext, IoMetrics, IoTarget, Snapshot, config::ConfigHandle};
+use std::sync::Arc;
 use ringest_error::Result;
 
+#[allow(dead_code)]
 pub struct PendingRead {
     offset: u64,
     len: u64,
 }
 
 pub struct BufferReader<T: IoTarget> {
-    context: Arc<IoContext<T>>,
+    pub(crate) context: Arc<IoContext<T>>,
 }
 
 impl<T: IoTarget> BufferReader<T> {
@@ -19,6 +20,30 @@ impl<T: IoTarget> BufferReader<T> {
         }
     }
 
+    pub fn config(&self) -> ConfigHandle {
+        self.context.config.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<IoMetrics> {
+        Arc::clone(&self.context.metrics)
+    }
+
+    /// Length of the target, counting writes that are still queued
+    pub async fn len(&self) -> Result<u64> {
+        self.context.len().await
+    }
+
+    /// Reads several `(offset, len)` ranges at once, sending the target a single vectored read.
+    pub async fn read_vectored_at(&self, ranges: &[(u64, u64)]) -> Result<Vec<Bytes>> {
+        self.context.read_vectored_at(ranges).await
+    }
+
+    /// Freezes the current contents of the target, queued writes included, see [`Snapshot`].
+    pub async fn snapshot(&self) -> Snapshot<T> {
+        let state = self.context.snapshot().await;
+        Snapshot::new(Arc::clone(&self.context), state)
+    }
+
     pub async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
         Arc::clone(&self.context).read_at(offset, len).await
         // {
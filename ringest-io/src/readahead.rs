@@ -0,0 +1,121 @@
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+
+struct Buffer {
+    offset: u64,
+    data: Bytes,
+    /// Write epoch of the target when the data was read
+    epoch: u64,
+}
+
+impl Buffer {
+    fn end(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Where the previous read ended; a read starting here is sequential
+    last_end: u64,
+    window: u64,
+    buffer: Option<Buffer>,
+    in_flight: bool,
+    /// Bumped on random access, so prefetches for an abandoned stream are dropped
+    generation: u64,
+}
+
+/// Sequential access detection and prefetched data for one target.
+///
+/// Every sequential read doubles the prefetch window, up to the configured maximum;
+/// a read anywhere else drops the buffer and starts over.
+#[derive(Default)]
+pub(crate) struct Readahead {
+    state: Mutex<State>,
+}
+
+/// Range a prefetch should read, and the stream it belongs to.
+pub(crate) struct Prefetch {
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+    pub(crate) generation: u64,
+}
+
+impl Readahead {
+    /// Prefetched bytes of `[offset, offset + len)`, if the buffer holds all of them
+    /// and the target has not been written since they were read.
+    pub(crate) fn lookup(&self, offset: u64, len: u64, epoch: u64) -> Option<Bytes> {
+        let mut state = self.state.lock();
+        let buffer = state.buffer.as_ref()?;
+        if buffer.epoch != epoch {
+            state.buffer = None;
+            return None;
+        }
+        if offset < buffer.offset || offset + len > buffer.end() {
+            return None;
+        }
+
+        let start = (offset - buffer.offset) as usize;
+        Some(buffer.data.slice(start..start + len as usize))
+    }
+
+    /// Records a read and returns what to prefetch next, if anything.
+    pub(crate) fn observe(&self, offset: u64, len: u64, max_window: u64) -> Option<Prefetch> {
+        let end = offset + len;
+        let mut state = self.state.lock();
+
+        if offset != state.last_end || max_window == 0 {
+            state.last_end = end;
+            state.window = 0;
+            state.buffer = None;
+            state.in_flight = false;
+            state.generation += 1;
+            return None;
+        }
+
+        state.last_end = end;
+        state.window = match state.window {
+            0 => len.max(1) * 2,
+            window => window * 2,
+        }.min(max_window);
+        if state.in_flight { return None; }
+
+        let ahead_from = match &state.buffer {
+            Some(buffer) if buffer.end() > end => buffer.end(),
+            _ => end,
+        };
+        if ahead_from - end >= state.window / 2 { return None; }
+
+        state.in_flight = true;
+        Some(Prefetch { offset: ahead_from, len: state.window, generation: state.generation })
+    }
+
+    /// Drops the buffer and any prefetch in flight.
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock();
+        state.buffer = None;
+        state.in_flight = false;
+        state.generation += 1;
+    }
+
+    /// Stores the result of a prefetch started at write epoch `epoch`.
+    pub(crate) fn complete(&self, prefetch: Prefetch, data: Option<Bytes>, epoch: u64) {
+        let mut state = self.state.lock();
+        if state.generation != prefetch.generation { return; }
+        state.in_flight = false;
+        let Some(data) = data else { return };
+
+        // Keep what is still ahead of the reader and append the new data to it.
+        let merged = match state.buffer.take() {
+            Some(buffer) if buffer.epoch == epoch && buffer.end() == prefetch.offset && buffer.end() > state.last_end => {
+                let kept = buffer.data.slice((state.last_end.max(buffer.offset) - buffer.offset) as usize..);
+                let mut joined = BytesMut::with_capacity(kept.len() + data.len());
+                joined.extend_from_slice(&kept);
+                joined.extend_from_slice(&data);
+                Buffer { offset: prefetch.offset - kept.len() as u64, data: joined.freeze(), epoch }
+            }
+            _ => Buffer { offset: prefetch.offset, data, epoch },
+        };
+        state.buffer = Some(merged);
+    }
+}
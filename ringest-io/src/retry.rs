@@ -0,0 +1,148 @@
+use std::{fmt, hash::BuildHasher, sync::Arc, time::Duration};
+use bytes::Bytes;
+use ringest_error::{Error, Result};
+
+/// Decides whether an error from the target is worth another attempt.
+pub type RetryClassifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+/// How many times a failed target write is attempted, and how long to wait in between.
+///
+/// The delay doubles after every attempt, starting at `base_delay` and capped at
+/// `max_delay`. With jitter enabled the actual wait is picked between half and the
+/// full delay, so writers that failed together do not retry in lockstep.
+///
+/// The default policy makes a single attempt.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    classifier: RetryClassifier,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// `max_attempts` counts the first try; by default `Error::Io` and `Error::Timeout` are retried.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            jitter: true,
+            classifier: Arc::new(|e| matches!(e, Error::Io(_) | Error::Timeout)),
+        }
+    }
+
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.classifier = Arc::new(classifier);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn is_retriable(&self, error: &Error) -> bool {
+        (self.classifier)(error)
+    }
+
+    /// Wait before attempt number `attempt + 1`
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(1u32 << attempt.saturating_sub(1).min(31));
+        let delay = exp.min(self.max_delay);
+        if !self.jitter || delay.is_zero() {
+            return delay
+        }
+
+        let half = delay / 2;
+        let spread = (delay - half).as_nanos() as u64;
+        let random = std::collections::hash_map::RandomState::new().hash_one(attempt);
+        half + Duration::from_nanos(random % (spread + 1))
+    }
+
+    pub(crate) async fn run<F, Fut, R>(&self, mut op: F) -> Result<R>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_attempts && self.is_retriable(&e) => {
+                    tokio::time::sleep(self.delay_for(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A flushed write that failed for good.
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub offset: u64,
+    pub data: Bytes,
+    pub error: Error,
+}
+
+/// Receives writes that could not reach the target after all retries.
+///
+/// Without a handler such writes go back to the head of the queue and the flush fails.
+#[derive(Clone)]
+pub struct DeadLetterHandler(Arc<dyn Fn(DeadLetter) + Send + Sync>);
+
+impl DeadLetterHandler {
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(DeadLetter) + Send + Sync + 'static,
+    {
+        Self(Arc::new(handler))
+    }
+
+    pub(crate) fn handle(&self, letter: DeadLetter) {
+        (self.0)(letter)
+    }
+}
+
+impl fmt::Debug for DeadLetterHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DeadLetterHandler")
+    }
+}
@@ -0,0 +1,49 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use ringest_error::{Error, Result};
+use crate::{ErasedContext, Registry};
+
+/// What [`Registry::shutdown`] did to each target.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// Outcome of closing each target, by id
+    pub targets: BTreeMap<u64, Result<()>>,
+}
+
+impl ShutdownReport {
+    /// Whether every target was closed with its queue written out
+    pub fn is_clean(&self) -> bool {
+        self.targets.values().all(Result::is_ok)
+    }
+
+    /// Targets that could not be closed, with why
+    pub fn failures(&self) -> impl Iterator<Item = (u64, &Error)> {
+        self.targets.iter().filter_map(|(id, result)| result.as_ref().err().map(|e| (*id, e)))
+    }
+}
+
+impl Registry {
+    /// Closes every target at once: new writes fail with `Error::Closed`, writes under way
+    /// finish, and each queue is written out and synced.
+    ///
+    /// A target not done within `deadline` is reported as `Error::Timeout`. Its flush is not
+    /// cut short, since that could lose queued writes, but carries on in the background.
+    /// Targets stay registered, so they can still be read, or flushed again after a failure.
+    pub async fn shutdown(&self, deadline: Duration) -> ShutdownReport {
+        let deadline = tokio::time::Instant::now() + deadline;
+        let targets: Vec<Arc<dyn ErasedContext>> = self.targets.iter().map(|entry| entry.value().clone()).collect();
+        let closing: Vec<_> = targets.into_iter()
+            .map(|ctx| (ctx.id(), tokio::spawn(async move { ctx.close().await })))
+            .collect();
+
+        let mut report = BTreeMap::new();
+        for (id, task) in closing {
+            let result = match tokio::time::timeout_at(deadline, task).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(e)) => Err(Error::Internal(e.to_string())),
+                Err(_) => Err(Error::Timeout),
+            };
+            report.insert(id, result);
+        }
+        ShutdownReport { targets: report }
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::{Arc, atomic::Ordering};
+use bytes::{Bytes, BytesMut};
+use parking_lot::Mutex;
+use ringest_error::{Error, Result};
+use crate::{IoContext, IoMetrics, IoTarget, IoTimeoutExt, interval::IntervalMap};
+
+/// Read-only view of a target as it was when [`BufferReader::snapshot`](crate::BufferReader::snapshot)
+/// was called.
+///
+/// Writes made afterwards are not visible in it. Before the context overwrites a range of the
+/// target, by a flush, a direct write or a truncation, it copies the old contents into every
+/// live snapshot, which keeps them until it is dropped. Writes queued when the snapshot was
+/// taken are shared with the queue rather than copied.
+///
+/// The copies are bounded by the target's `snapshot_limit`. A snapshot that would go over it
+/// (or whose old contents could not be read) expires: it drops its copies and its reads fail
+/// with `Error::SnapshotExpired`, while writers carry on unaffected.
+pub struct Snapshot<T: IoTarget> {
+    context: Arc<IoContext<T>>,
+    state: Arc<SnapshotState>,
+}
+
+impl<T: IoTarget> Snapshot<T> {
+    pub(crate) fn new(context: Arc<IoContext<T>>, state: Arc<SnapshotState>) -> Self {
+        Self { context, state }
+    }
+
+    /// Length of the target when the snapshot was taken, if the target reports one
+    pub fn size(&self) -> Option<u64> {
+        self.state.len
+    }
+
+    /// Bytes this snapshot keeps alive, counting the queued writes it shares
+    pub fn memory_usage(&self) -> u64 {
+        self.state.queued.total_bytes() + self.state.preserved.lock().as_ref().map_or(0, IntervalMap::total_bytes)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.state.is_expired()
+    }
+
+    /// Reads `len` bytes as they were when the snapshot was taken. Bytes past its size read as zeros.
+    pub async fn read_at(&self, offset: u64, len: u64) -> Result<Bytes> {
+        let read_timeout = self.context.config.read().read_timeout;
+        let current = self.context.target.read_at(offset, len as usize)
+            .with_timeout(read_timeout)
+            .await?;
+
+        // Looked at only after the read, since old contents are saved before the target changes.
+        let mut buf = BytesMut::from(&current[..]);
+        buf.resize(len as usize, 0);
+        {
+            let preserved = self.state.preserved.lock();
+            let Some(preserved) = preserved.as_ref() else { return Err(Error::SnapshotExpired) };
+            overlay(&mut buf, offset, preserved);
+        }
+        overlay(&mut buf, offset, &self.state.queued);
+
+        if let Some(size) = self.state.len {
+            let end = size.saturating_sub(offset).min(len) as usize;
+            buf[end..].fill(0);
+        }
+        Ok(buf.freeze())
+    }
+}
+
+/// What a [`Snapshot`] holds, shared with the context that keeps it up to date.
+pub(crate) struct SnapshotState {
+    len: Option<u64>,
+    /// Writes that were queued when the snapshot was taken
+    queued: IntervalMap,
+    /// Old contents of target ranges overwritten since, or `None` once expired
+    preserved: Mutex<Option<IntervalMap>>,
+    limit: u64,
+    metrics: Arc<IoMetrics>,
+}
+
+impl SnapshotState {
+    pub(crate) fn new(len: Option<u64>, queued: IntervalMap, limit: u64, metrics: Arc<IoMetrics>) -> Self {
+        metrics.snapshot_bytes.fetch_add(queued.total_bytes(), Ordering::Relaxed);
+        Self {
+            len,
+            queued,
+            preserved: Mutex::new(Some(IntervalMap::new())),
+            limit,
+            metrics,
+        }
+    }
+
+    pub(crate) fn len(&self) -> Option<u64> {
+        self.len
+    }
+
+    pub(crate) fn is_expired(&self) -> bool {
+        self.preserved.lock().is_none()
+    }
+
+    /// Keeps the bytes of `data`, read from the target at `offset`, that it does not hold yet.
+    pub(crate) fn preserve(&self, offset: u64, data: &[u8]) {
+        let end = (offset + data.len() as u64).min(self.len.unwrap_or(u64::MAX));
+        if end <= offset { return; }
+
+        let mut preserved = self.preserved.lock();
+        let Some(map) = preserved.as_mut() else { return };
+
+        // Only the first version of a byte is the one the snapshot saw.
+        let mut holes = Vec::new();
+        let mut cursor = offset;
+        for (start, seg) in map.overlapping(offset, end) {
+            if start > cursor {
+                holes.push((cursor, start));
+            }
+            cursor = cursor.max(start + seg.data.len() as u64);
+        }
+        if cursor < end {
+            holes.push((cursor, end));
+        }
+
+        let added: u64 = holes.iter().map(|(start, stop)| stop - start).sum();
+        if map.total_bytes() + added > self.limit {
+            drop(preserved);
+            self.expire();
+            return;
+        }
+        for (start, stop) in holes {
+            let piece = &data[(start - offset) as usize..(stop - offset) as usize];
+            map.insert(start, Bytes::copy_from_slice(piece), 0);
+        }
+        self.metrics.snapshot_bytes.fetch_add(added, Ordering::Relaxed);
+    }
+
+    /// Drops the preserved bytes; reads fail from now on.
+    pub(crate) fn expire(&self) {
+        if let Some(map) = self.preserved.lock().take() {
+            self.metrics.snapshot_bytes.fetch_sub(map.total_bytes(), Ordering::Relaxed);
+            self.metrics.expired_snapshots.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for SnapshotState {
+    fn drop(&mut self) {
+        let preserved = self.preserved.get_mut().as_ref().map_or(0, IntervalMap::total_bytes);
+        self.metrics.snapshot_bytes.fetch_sub(self.queued.total_bytes() + preserved, Ordering::Relaxed);
+    }
+}
+
+/// Copies the parts of `map` that fall within `buf`, which holds the bytes starting at `offset`.
+fn overlay(buf: &mut [u8], offset: u64, map: &IntervalMap) {
+    let end = offset + buf.len() as u64;
+    for (start, seg) in map.overlapping(offset, end) {
+        let from = start.max(offset);
+        let to = (start + seg.data.len() as u64).min(end);
+        buf[(from - offset) as usize..(to - offset) as usize]
+            .copy_from_slice(&seg.data[(from - start) as usize..(to - start) as usize]);
+    }
+}
@@ -1,7 +1,5 @@
 use std::{sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use tokio::time;
-
 pub struct TimeCache {
     current_ms: Arc<AtomicU64>,
 }
@@ -11,14 +9,11 @@ impl TimeCache {
         let current_ms = Arc::new(AtomicU64::new(Self::now_sys()));
         let current_ms_clone = Arc::clone(&current_ms);
 
-        tokio::spawn(async move {
-            let mut interval = time::interval(tick_interval);
-            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
-
-            loop {
-                interval.tick().await;
-                current_ms_clone.store(Self::now_sys(), Ordering::Relaxed);
-            }
+        // A thread rather than a task, so the clock keeps going after the runtime that
+        // first touched it shuts down.
+        std::thread::spawn(move || loop {
+            std::thread::sleep(tick_interval);
+            current_ms_clone.store(Self::now_sys(), Ordering::Relaxed);
         });
 
         Self {
@@ -0,0 +1,59 @@
+//! Helpers for the optional `tracing` instrumentation; without the feature they compile to nothing.
+
+use ringest_error::Result;
+#[cfg(feature = "tracing")]
+use ringest_error::Error;
+
+/// Debug event in the current span.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+/// Sets a field declared as `Empty` on the current span.
+macro_rules! record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+    };
+}
+
+pub(crate) use {event, record};
+
+/// Records `queue_depth`, `outcome` and, on failure, `error` on the current span.
+#[cfg(feature = "tracing")]
+pub(crate) fn finish<R>(result: &Result<R>, queue_depth: impl FnOnce() -> u64) {
+    let span = tracing::Span::current();
+    span.record("queue_depth", queue_depth());
+    match result {
+        Ok(_) => span.record("outcome", "ok"),
+        Err(Error::Timeout) => span.record("outcome", "timeout"),
+        Err(e) => span.record("outcome", "error").record("error", tracing::field::display(e)),
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn finish<R>(_result: &Result<R>, _queue_depth: impl FnOnce() -> u64) {}
+
+/// Runs `f` on the blocking pool. The `blocking` span's `waited_us` is how long it sat
+/// in the pool queue, so the hop can be told apart from the call itself.
+pub(crate) async fn blocking<R: Send + 'static>(
+    op: &'static str,
+    f: impl FnOnce() -> std::io::Result<R> + Send + 'static,
+) -> Result<R> {
+    #[cfg(feature = "tracing")]
+    let (span, queued) = (tracing::debug_span!("blocking", op, waited_us = tracing::field::Empty), std::time::Instant::now());
+    #[cfg(not(feature = "tracing"))]
+    let _ = op;
+
+    let result = tokio::task::spawn_blocking(move || {
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        record!("waited_us", queued.elapsed().as_micros() as u64);
+        f()
+    }).await.map_err(|_| std::io::Error::other("Join error"))?;
+    Ok(result?)
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use bytes::Bytes;
+use ringest_error::Result;
+use crate::{IoContext, IoTarget};
+
+/// Writes that reach a target's queue together, or not at all.
+///
+/// Nothing is queued until [`Transaction::commit`]; dropping the transaction without
+/// committing discards its writes. Once committed, concurrent reads see either none
+/// or all of them. With a WAL the transaction is logged as a single record, so it is
+/// recovered whole after a crash or not at all. Without one, a crash while the queue
+/// is being flushed could leave part of it on the target, so transactions are refused
+/// when the target has a durability policy but no WAL. Under [`Durability::None`]
+/// nothing is promised across a crash and they only keep the visibility guarantee.
+///
+/// [`Durability::None`]: crate::Durability::None
+#[must_use = "a transaction does nothing unless committed"]
+pub struct Transaction<T: IoTarget> {
+    context: Arc<IoContext<T>>,
+    writes: Vec<(u64, Bytes)>,
+}
+
+impl<T: IoTarget> Transaction<T> {
+    pub(crate) fn new(context: Arc<IoContext<T>>) -> Self {
+        Self {
+            context,
+            writes: Vec::new(),
+        }
+    }
+
+    /// Stages a write. Where staged writes overlap, the later one wins.
+    pub fn write_at(&mut self, offset: u64, data: impl Into<Bytes>) -> &mut Self {
+        self.writes.push((offset, data.into()));
+        self
+    }
+
+    /// Number of staged writes
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Queues every staged write at once.
+    pub async fn commit(self) -> Result<()> {
+        self.context.commit(self.writes).await
+    }
+
+    /// Discards the staged writes, same as dropping the transaction.
+    pub fn rollback(self) {}
+}
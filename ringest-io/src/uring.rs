@@ -0,0 +1,375 @@
+use std::{collections::VecDeque, fs::File, io, os::fd::AsRawFd, path::Path, sync::{Arc, mpsc}, thread};
+use async_trait::async_trait;
+use bytes::Bytes;
+use io_uring::{IoUring, opcode, types};
+use ringest_error::Result;
+use tokio::sync::oneshot;
+use crate::{IoTarget, SyncMode};
+
+const RING_ENTRIES: u32 = 256;
+const REGISTERED_BUFFERS: usize = 64;
+const REGISTERED_BUFFER_SIZE: usize = 64 * 1024;
+/// Most buffers in one `writev`, as limited by `IOV_MAX`
+const MAX_IOVECS: usize = 1024;
+
+enum Op {
+    Read { offset: u64, len: usize },
+    /// Adjacent buffers written from `offset` on
+    Write { offset: u64, data: Vec<Bytes> },
+    Sync(SyncMode),
+}
+
+struct Request {
+    op: Op,
+    reply: oneshot::Sender<io::Result<Bytes>>,
+}
+
+/// [`IoTarget`] that sends positional reads and writes to a file through io_uring.
+///
+/// A driver thread owns the ring. Operations that fit a registered buffer use the
+/// fixed-buffer opcodes, larger ones are submitted straight from their own memory.
+/// A batch of writes from a flush is pushed to the ring as a whole before it is submitted,
+/// and each run of adjacent buffers becomes a single `writev`.
+pub struct UringTarget {
+    file: Arc<File>,
+    requests: mpsc::Sender<Vec<Request>>,
+}
+
+impl UringTarget {
+    /// Opens (or creates) `path` for reading and writing.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::options().read(true).write(true).create(true).truncate(false).open(path)?;
+        Self::new(file)
+    }
+
+    pub fn new(file: File) -> Result<Self> {
+        let file = Arc::new(file);
+        let driver = Driver::new(file.clone())?;
+        let (requests, incoming) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("ringest-uring".to_string())
+            .spawn(move || driver.run(incoming))?;
+
+        Ok(Self { file, requests })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    async fn submit(&self, ops: Vec<Op>) -> Vec<io::Result<Bytes>> {
+        let (requests, replies): (Vec<_>, Vec<_>) = ops.into_iter().map(|op| {
+            let (reply, rx) = oneshot::channel();
+            (Request { op, reply }, rx)
+        }).unzip();
+
+        let stopped = || io::Error::other("io_uring driver stopped");
+        if self.requests.send(requests).is_err() {
+            return replies.iter().map(|_| Err(stopped())).collect();
+        }
+
+        let mut results = Vec::with_capacity(replies.len());
+        for reply in replies {
+            results.push(reply.await.unwrap_or_else(|_| Err(stopped())));
+        }
+        results
+    }
+
+    async fn submit_one(&self, op: Op) -> io::Result<Bytes> {
+        self.submit(vec![op]).await.pop().unwrap_or_else(|| Err(io::Error::other("missing completion")))
+    }
+}
+
+#[async_trait]
+impl IoTarget for UringTarget {
+    async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+        Ok(self.submit_one(Op::Read { offset, len }).await?)
+    }
+
+    async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+        self.submit_one(Op::Write { offset, data: vec![content] }).await?;
+        Ok(())
+    }
+
+    async fn write_vectored_at(&self, writes: &[(u64, Bytes)]) -> Result<()> {
+        self.write_batch_at(&[writes.to_vec()]).await.pop().unwrap_or(Ok(()))
+    }
+
+    async fn read_vectored_at(&self, ranges: &[(u64, usize)]) -> Result<Vec<Bytes>> {
+        let ops = ranges.iter().map(|(offset, len)| Op::Read { offset: *offset, len: *len }).collect();
+        Ok(self.submit(ops).await.into_iter().collect::<io::Result<_>>()?)
+    }
+
+    async fn sync(&self, mode: SyncMode) -> Result<()> {
+        self.submit_one(Op::Sync(mode)).await?;
+        Ok(())
+    }
+
+    async fn len(&self) -> Result<u64> {
+        let file = self.file.clone();
+        let len = tokio::task::spawn_blocking(move || file.metadata().map(|meta| meta.len()))
+            .await.map_err(|_| io::Error::other("Join error"))??;
+        Ok(len)
+    }
+
+    async fn set_len(&self, len: u64) -> Result<()> {
+        let file = self.file.clone();
+        tokio::task::spawn_blocking(move || File::set_len(&file, len))
+            .await.map_err(|_| io::Error::other("Join error"))??;
+        Ok(())
+    }
+
+    async fn write_batch_at(&self, batch: &[Vec<(u64, Bytes)>]) -> Vec<Result<()>> {
+        // One op per run of adjacent pairs, remembering which entry of `batch` it belongs to.
+        let mut owners = Vec::new();
+        let mut ops = Vec::new();
+        for (i, writes) in batch.iter().enumerate() {
+            for run in writes.chunk_by(|a, b| a.0 + a.1.len() as u64 == b.0) {
+                for chunk in run.chunks(MAX_IOVECS) {
+                    owners.push(i);
+                    ops.push(Op::Write { offset: chunk[0].0, data: chunk.iter().map(|(_, data)| data.clone()).collect() });
+                }
+            }
+        }
+
+        let mut results: Vec<Result<()>> = batch.iter().map(|_| Ok(())).collect();
+        for (i, result) in owners.into_iter().zip(self.submit(ops).await) {
+            if let (Ok(()), Err(e)) = (&results[i], result) {
+                results[i] = Err(e.into());
+            }
+        }
+        results
+    }
+}
+
+/// Memory an operation uses until it completes.
+enum Memory {
+    Registered(u16),
+    Read(Vec<u8>),
+    /// Only held so the data and the iovecs pointing at it stay alive
+    #[allow(dead_code)]
+    Write(Vec<Bytes>, IoVecs),
+    None,
+}
+
+/// iovecs pointing into the `Bytes` kept next to them in [`Memory::Write`].
+struct IoVecs(#[allow(dead_code)] Vec<libc::iovec>);
+
+// Safety: the pointers are only read by the kernel, and the memory they point to is `Send`.
+unsafe impl Send for IoVecs {}
+
+enum Kind {
+    Read,
+    Write,
+    Sync,
+}
+
+struct InFlight {
+    reply: oneshot::Sender<io::Result<Bytes>>,
+    kind: Kind,
+    memory: Memory,
+    len: usize,
+}
+
+struct Driver {
+    // Declared first so the ring is closed before the registered buffers are freed.
+    ring: IoUring,
+    file: Arc<File>,
+    buffers: Vec<Vec<u8>>,
+    free_buffers: Vec<u16>,
+    in_flight: Vec<Option<InFlight>>,
+    free_slots: Vec<usize>,
+    backlog: VecDeque<Request>,
+    retired: Vec<Memory>,
+}
+
+impl Driver {
+    fn new(file: Arc<File>) -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let mut buffers: Vec<Vec<u8>> = (0..REGISTERED_BUFFERS).map(|_| vec![0u8; REGISTERED_BUFFER_SIZE]).collect();
+        let iovecs: Vec<libc::iovec> = buffers.iter_mut().map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        }).collect();
+
+        // Safety: the buffers are never resized and outlive the ring (see field order).
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+
+        Ok(Self {
+            ring,
+            file,
+            buffers,
+            free_buffers: (0..REGISTERED_BUFFERS as u16).rev().collect(),
+            in_flight: Vec::new(),
+            free_slots: Vec::new(),
+            backlog: VecDeque::new(),
+            retired: Vec::new(),
+        })
+    }
+
+    fn pending(&self) -> usize {
+        self.in_flight.len() - self.free_slots.len()
+    }
+
+    fn run(mut self, incoming: mpsc::Receiver<Vec<Request>>) {
+        let mut open = true;
+        loop {
+            // Block for new work only while the ring is idle.
+            if self.pending() == 0 && self.backlog.is_empty() {
+                if !open { return; }
+                match incoming.recv() {
+                    Ok(batch) => self.backlog.extend(batch),
+                    Err(_) => return,
+                }
+            }
+            while open {
+                match incoming.try_recv() {
+                    Ok(batch) => self.backlog.extend(batch),
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => open = false,
+                }
+            }
+
+            self.push_backlog();
+            // Everything taken was rejected before reaching the ring, so there is nothing to wait for.
+            if self.pending() == 0 { continue; }
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.fail_all(e);
+                    return;
+                }
+            }
+            self.reap();
+        }
+    }
+
+    fn push_backlog(&mut self) {
+        let fd = types::Fd(self.file.as_raw_fd());
+
+        while self.pending() < RING_ENTRIES as usize {
+            let Some(Request { op, reply }) = self.backlog.pop_front() else { break };
+            let too_long = match &op {
+                Op::Read { len, .. } => *len > u32::MAX as usize,
+                Op::Write { data, .. } => data.iter().map(Bytes::len).sum::<usize>() > u32::MAX as usize,
+                Op::Sync(_) => false,
+            };
+            if too_long {
+                let _ = reply.send(Err(io::Error::new(io::ErrorKind::InvalidInput, "operation longer than u32::MAX bytes")));
+                continue;
+            }
+
+            let (entry, kind, memory, len) = match op {
+                Op::Read { offset, len } => match self.take_buffer(len) {
+                    Some(i) => {
+                        let ptr = self.buffers[i as usize].as_mut_ptr();
+                        let entry = opcode::ReadFixed::new(fd, ptr, len as u32, i).offset(offset).build();
+                        (entry, Kind::Read, Memory::Registered(i), len)
+                    }
+                    None => {
+                        let mut buf = vec![0u8; len];
+                        let entry = opcode::Read::new(fd, buf.as_mut_ptr(), len as u32).offset(offset).build();
+                        (entry, Kind::Read, Memory::Read(buf), len)
+                    }
+                },
+                Op::Write { offset, data } => {
+                    let len = data.iter().map(Bytes::len).sum();
+                    match self.take_buffer(len) {
+                        Some(i) => {
+                            let buf = &mut self.buffers[i as usize];
+                            let mut at = 0;
+                            for piece in &data {
+                                buf[at..at + piece.len()].copy_from_slice(piece);
+                                at += piece.len();
+                            }
+                            let entry = opcode::WriteFixed::new(fd, buf.as_ptr(), len as u32, i).offset(offset).build();
+                            (entry, Kind::Write, Memory::Registered(i), len)
+                        }
+                        None => {
+                            let iovecs: Vec<libc::iovec> = data.iter().map(|piece| libc::iovec {
+                                iov_base: piece.as_ptr() as *mut _,
+                                iov_len: piece.len(),
+                            }).collect();
+                            let entry = opcode::Writev::new(fd, iovecs.as_ptr(), iovecs.len() as u32).offset(offset).build();
+                            (entry, Kind::Write, Memory::Write(data, IoVecs(iovecs)), len)
+                        }
+                    }
+                }
+                Op::Sync(mode) => {
+                    let flags = match mode {
+                        SyncMode::Data => types::FsyncFlags::DATASYNC,
+                        SyncMode::All => types::FsyncFlags::empty(),
+                    };
+                    (opcode::Fsync::new(fd).flags(flags).build(), Kind::Sync, Memory::None, 0)
+                }
+            };
+
+            let slot = self.free_slots.pop().unwrap_or_else(|| {
+                self.in_flight.push(None);
+                self.in_flight.len() - 1
+            });
+            self.in_flight[slot] = Some(InFlight { reply, kind, memory, len });
+
+            // Safety: the memory behind the entry is kept in `in_flight` until it completes,
+            // and the queue has room because no more than RING_ENTRIES operations are pending.
+            unsafe {
+                self.ring.submission().push(&entry.user_data(slot as u64))
+                    .expect("submission queue has room for every pending operation");
+            }
+        }
+    }
+
+    /// Index of a free registered buffer that holds `len` bytes.
+    fn take_buffer(&mut self, len: usize) -> Option<u16> {
+        if len > REGISTERED_BUFFER_SIZE { return None; }
+        self.free_buffers.pop()
+    }
+
+    fn reap(&mut self) {
+        let completions: Vec<(u64, i32)> = self.ring.completion().map(|cqe| (cqe.user_data(), cqe.result())).collect();
+
+        for (slot, res) in completions {
+            let Some(op) = self.in_flight[slot as usize].take() else { continue };
+            self.free_slots.push(slot as usize);
+
+            let result = if res < 0 {
+                Err(io::Error::from_raw_os_error(-res))
+            } else {
+                let done = res as usize;
+                match op.kind {
+                    // Like the `std::fs::File` impl, a read past the end is padded with zeros.
+                    Kind::Read => {
+                        let mut data = match &op.memory {
+                            Memory::Registered(i) => self.buffers[*i as usize][..done].to_vec(),
+                            Memory::Read(buf) => buf[..done].to_vec(),
+                            _ => Vec::new(),
+                        };
+                        data.resize(op.len, 0);
+                        Ok(Bytes::from(data))
+                    }
+                    Kind::Write if done < op.len => Err(io::Error::new(io::ErrorKind::WriteZero, "short write")),
+                    Kind::Write | Kind::Sync => Ok(Bytes::new()),
+                }
+            };
+
+            if let Memory::Registered(i) = op.memory {
+                self.free_buffers.push(i);
+            }
+            let _ = op.reply.send(result);
+        }
+    }
+
+    fn fail_all(&mut self, error: io::Error) {
+        let mut replies: Vec<_> = self.backlog.drain(..).map(|request| request.reply).collect();
+        for op in self.in_flight.drain(..).flatten() {
+            // The kernel may still use this memory until the ring is closed.
+            self.retired.push(op.memory);
+            replies.push(op.reply);
+        }
+        for reply in replies {
+            let _ = reply.send(Err(io::Error::new(error.kind(), error.to_string())));
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::{fs::File, io::{Read, Write}, path::{Path, PathBuf}, sync::Arc};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use parking_lot::Mutex;
+use ringest_error::{Error, Result};
+use crate::PendingWrite;
+
+/// Record header: payload length (u32) followed by the payload CRC32 (u32).
+const HEADER_LEN: usize = 8;
+/// Payload header: record kind (u8) followed by the target offset (u64), or for a
+/// batch the number of writes, each of them an offset (u64), a length (u64) and the data.
+const PAYLOAD_HEADER_LEN: usize = 9;
+
+const KIND_WRITE: u8 = 1;
+const KIND_BATCH: u8 = 2;
+
+/// Append-only log of queued writes for a single target.
+///
+/// Every record is synced before [`WriteAheadLog::append`] returns, so a write
+/// acknowledged by `IoContext::write_at` survives a crash even if it never
+/// left the `WriteQueue`. Once a flush has reached the target, the log is
+/// compacted down to the writes that are still queued.
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+    /// Held across "append to log + push to queue" and across compaction, so
+    /// the log always contains exactly the writes that are still queued.
+    pub(crate) gate: tokio::sync::Mutex<()>,
+}
+
+impl WriteAheadLog {
+    /// Opens (or creates) the log at `path` and returns the writes recovered from it.
+    ///
+    /// Recovery stops at the first short or corrupted record. Everything after it is
+    /// a torn tail from an interrupted append and is cut off the file.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<PendingWrite>)> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)?;
+
+        let (recovered, valid_len) = decode_records(&raw);
+        if valid_len < raw.len() {
+            file.set_len(valid_len as u64)?;
+            file.sync_data()?;
+        }
+
+        Ok((Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+            gate: tokio::sync::Mutex::new(()),
+        }, recovered))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `op` to the log and waits until it is durable.
+    pub async fn append(&self, op: &PendingWrite) -> Result<()> {
+        self.append_all(std::slice::from_ref(op)).await
+    }
+
+    /// Appends `ops` as a single record and waits until it is durable. After a crash
+    /// they are recovered all together or, if the record is torn, not at all.
+    pub async fn append_all(&self, ops: &[PendingWrite]) -> Result<()> {
+        let mut record = BytesMut::new();
+        match ops {
+            [] => return Ok(()),
+            [op] => encode_record(&mut record, op),
+            ops => encode_batch(&mut record, ops),
+        }
+        let file = Arc::clone(&self.file);
+
+        tokio::task::spawn_blocking(move || append_durably(&mut file.lock(), &record))
+            .await.map_err(|e| Error::Internal(e.to_string()))??;
+
+        Ok(())
+    }
+
+    /// Replaces the log contents with `remaining`.
+    ///
+    /// The new log is written next to the old one and renamed over it, so a crash
+    /// during compaction leaves either the old or the new log, never a mix.
+    pub async fn reset(&self, remaining: &[PendingWrite]) -> Result<()> {
+        let mut records = BytesMut::new();
+        for op in remaining {
+            encode_record(&mut records, op);
+        }
+        let file = Arc::clone(&self.file);
+        let path = self.path.clone();
+
+        tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            let mut file = file.lock();
+            if records.is_empty() {
+                file.set_len(0)?;
+                return file.sync_data()
+            }
+
+            let mut tmp_path = path.clone().into_os_string();
+            tmp_path.push(".tmp");
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(&records)?;
+            tmp.sync_data()?;
+            drop(tmp);
+
+            std::fs::rename(&tmp_path, &path)?;
+            sync_parent(&path)?;
+            *file = File::options().read(true).append(true).open(&path)?;
+            Ok(())
+        }).await.map_err(|e| Error::Internal(e.to_string()))??;
+
+        Ok(())
+    }
+}
+
+/// Appends `record` to `file` and syncs it. On failure the file is cut back to its old
+/// length, so a torn frame never sits in front of records appended later.
+pub(crate) fn append_durably(file: &mut File, record: &[u8]) -> std::io::Result<()> {
+    let len = file.metadata()?.len();
+    let result = file.write_all(record).and_then(|()| file.sync_data());
+    if result.is_err() {
+        let _ = file.set_len(len);
+    }
+    result
+}
+
+/// Makes a rename in the directory holding `path` durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories cannot be opened for syncing here; the rename is left to the file system.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn encode_record(buf: &mut BytesMut, op: &PendingWrite) {
+    let mut payload = BytesMut::with_capacity(PAYLOAD_HEADER_LEN + op.data.len());
+    payload.put_u8(KIND_WRITE);
+    payload.put_u64_le(op.offset);
+    payload.put_slice(&op.data);
+    frame(buf, &payload);
+}
+
+fn encode_batch(buf: &mut BytesMut, ops: &[PendingWrite]) {
+    let data_len: usize = ops.iter().map(|op| 16 + op.data.len()).sum();
+    let mut payload = BytesMut::with_capacity(PAYLOAD_HEADER_LEN + data_len);
+    payload.put_u8(KIND_BATCH);
+    payload.put_u64_le(ops.len() as u64);
+    for op in ops {
+        payload.put_u64_le(op.offset);
+        payload.put_u64_le(op.data.len() as u64);
+        payload.put_slice(&op.data);
+    }
+    frame(buf, &payload);
+}
+
+/// Appends `payload` to `buf` behind its length and CRC.
+pub(crate) fn frame(buf: &mut BytesMut, payload: &[u8]) {
+    buf.put_u32_le(payload.len() as u32);
+    buf.put_u32_le(crc32fast::hash(payload));
+    buf.put_slice(payload);
+}
+
+/// Writes of a batch payload, after its kind byte, or `None` if it is malformed.
+fn decode_batch(mut cursor: &[u8]) -> Option<Vec<(u64, Bytes)>> {
+    let count = cursor.get_u64_le();
+    let mut ops = Vec::new();
+    for _ in 0..count {
+        if cursor.remaining() < 16 { return None; }
+        let offset = cursor.get_u64_le();
+        let len = cursor.get_u64_le() as usize;
+        if cursor.remaining() < len { return None; }
+        ops.push((offset, Bytes::copy_from_slice(&cursor[..len])));
+        cursor.advance(len);
+    }
+    Some(ops)
+}
+
+/// Payloads of the intact records at the start of `raw`, each with the position just past it.
+/// Stops at the first short or corrupted record.
+pub(crate) fn frames(raw: &[u8]) -> Vec<(&[u8], usize)> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+
+    while raw.len() - pos >= HEADER_LEN {
+        let mut header = &raw[pos..pos + HEADER_LEN];
+        let payload_len = header.get_u32_le() as usize;
+        let crc = header.get_u32_le();
+
+        let start = pos + HEADER_LEN;
+        if raw.len() - start < payload_len { break; }
+
+        let payload = &raw[start..start + payload_len];
+        if crc32fast::hash(payload) != crc { break; }
+
+        pos = start + payload_len;
+        frames.push((payload, pos));
+    }
+    frames
+}
+
+/// Decodes records from `raw` and returns them with the length of the valid prefix.
+fn decode_records(raw: &[u8]) -> (Vec<PendingWrite>, usize) {
+    let mut recovered = Vec::new();
+    let mut pos = 0;
+
+    for (payload, end) in frames(raw) {
+        if payload.len() < PAYLOAD_HEADER_LEN { break; }
+
+        let mut cursor = payload;
+        let ops = match cursor.get_u8() {
+            KIND_WRITE => {
+                let offset = cursor.get_u64_le();
+                vec![(offset, Bytes::copy_from_slice(cursor))]
+            }
+            KIND_BATCH => match decode_batch(cursor) {
+                Some(ops) => ops,
+                None => break,
+            },
+            _ => break,
+        };
+
+        // Records are appended in queue order, so their position is their sequence number.
+        for (offset, data) in ops {
+            let seq = recovered.len() as u64 + 1;
+            recovered.push(PendingWrite { offset, data, seq });
+        }
+        pos = end;
+    }
+
+    (recovered, pos)
+}
@@ -1,19 +1,18 @@
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use bytes::{BufMut, Bytes, BytesMut};
-use parking_lot::RwLock;
-use ringest_error::{Error, Result};
-use crate::{IoContext, IoTarget, IoTimeoutExt, LatencyMeasureExt, WriteQueue};
+use bytes::Bytes;
+use ringest_error::Result;
+use crate::{Durability, IoContext, IoMetrics, IoTarget, Transaction, config::ConfigHandle, ctx::SyncPoint};
 
 #[derive(Clone)]
 pub struct PendingWrite {
     pub(crate) offset: u64,
     pub(crate) data: Bytes,
+    /// Order in which the write was queued; the higher one wins where writes overlap
+    pub(crate) seq: u64,
 }
 
 pub struct BufferWriter<T: IoTarget> {
-    context: Arc<IoContext<T>>,
+    pub(crate) context: Arc<IoContext<T>>,
 }
 
 impl<T: IoTarget> BufferWriter<T> {
@@ -51,6 +50,25 @@ impl<T: IoTarget> BufferWriter<T> {
         // Ok(())
     }
 
+    /// Starts a transaction whose writes are queued together on commit.
+    ///
+    /// Fails with `TargetError::TransactionNeedsWal` if the target has a durability policy
+    /// but no WAL, since its commits could then not be made crash-atomic.
+    pub fn transaction(&self) -> Result<Transaction<T>> {
+        self.context.check_transactions()?;
+        Ok(Transaction::new(Arc::clone(&self.context)))
+    }
+
+    /// Length of the target, counting writes that are still queued
+    pub async fn len(&self) -> Result<u64> {
+        self.context.len().await
+    }
+
+    /// Truncates or zero-extends the target to `len` bytes, after the writes queued so far.
+    pub async fn set_len(&self, len: u64) -> Result<()> {
+        self.context.set_len(len).await
+    }
+
     pub async fn flush(&self) -> Result<()> {
         self.context.flush().await
         // let mut q: WriteQueue;
@@ -89,8 +107,25 @@ impl<T: IoTarget> BufferWriter<T> {
         // Ok(())
     }
 
+    /// Flushes the queue and syncs the target unless the policy is [`Durability::None`].
     pub async fn shutdown(&self) -> Result<()> {
-        self.context.flush().await
+        self.context.flush_queue(SyncPoint::Shutdown).await
+    }
+
+    pub fn config(&self) -> ConfigHandle {
+        self.context.config.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<IoMetrics> {
+        Arc::clone(&self.context.metrics)
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.context.config.read().durability
+    }
+
+    pub fn set_durability(&self, durability: Durability) {
+        self.context.config.set_durability(durability);
     }
 }
 
@@ -100,12 +135,12 @@ impl<T: IoTarget> Drop for BufferWriter<T> {
 
         if let Ok(handle) = tokio::runtime::Handle::try_current() {
             handle.spawn(async move {
-                let _ = ctx.flush().await;
+                let _ = ctx.flush_queue(SyncPoint::Auto).await;
             });
         } else {
             std::thread::spawn(move || {
                 if let Ok(rt) = tokio::runtime::Runtime::new() {
-                    rt.block_on(async { let _ = ctx.flush().await; });
+                    rt.block_on(async { let _ = ctx.flush_queue(SyncPoint::Auto).await; });
                 }
             });
         }
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use async_trait::async_trait;
     use bytes::Bytes;
-    use ringest_io::Registry;
-    use std::{sync::Arc, time::Duration};
+    use ringest_io::{BackpressureMode, DeadLetterHandler, Durability, Fault, FaultPlan, FaultyTarget, IoConfig, IoTarget, MemoryTarget, Registry, RetryPolicy, SyncMode};
+    use ringest_error::{Error, TargetError};
+    use ringest_error::Result;
+    use std::{io::Write, sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::Duration};
     use tokio::sync::Barrier;
 
     fn create_test_file(path: &str) -> std::fs::File {
@@ -16,7 +18,79 @@ mod tests {
         }
         #[cfg(not(windows))]
         {
-            std::fs::File::create(path).unwrap()
+            std::fs::OpenOptions::new()
+                .create(true).read(true).write(true).truncate(true)
+                .open(path).unwrap()
+        }
+    }
+
+    /// Lets `writes_left` writes through, then parks every later write forever,
+    /// like a process that died in the middle of a flush.
+    struct StallingTarget {
+        file: std::fs::File,
+        writes_left: AtomicUsize,
+    }
+
+    impl StallingTarget {
+        fn new(file: std::fs::File, writes_left: usize) -> Self {
+            Self { file, writes_left: AtomicUsize::new(writes_left) }
+        }
+    }
+
+    #[async_trait]
+    impl IoTarget for StallingTarget {
+        async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+            IoTarget::read_at(&self.file, offset, len).await
+        }
+
+        async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+            if self.writes_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_err() {
+                std::future::pending::<()>().await;
+            }
+            IoTarget::write_at(&self.file, content, offset).await
+        }
+    }
+
+    #[derive(Default)]
+    struct Counts {
+        writes: AtomicUsize,
+        data_syncs: AtomicUsize,
+        full_syncs: AtomicUsize,
+    }
+
+    impl Counts {
+        fn writes(&self) -> usize {
+            self.writes.load(Ordering::SeqCst)
+        }
+
+        fn syncs(&self) -> (usize, usize) {
+            (self.data_syncs.load(Ordering::SeqCst), self.full_syncs.load(Ordering::SeqCst))
+        }
+    }
+
+    /// Records the writes and syncs that reach the underlying file.
+    struct CountingTarget {
+        file: std::fs::File,
+        counts: Arc<Counts>,
+    }
+
+    #[async_trait]
+    impl IoTarget for CountingTarget {
+        async fn read_at(&self, offset: u64, len: usize) -> Result<Bytes> {
+            IoTarget::read_at(&self.file, offset, len).await
+        }
+
+        async fn write_at(&self, content: Bytes, offset: u64) -> Result<()> {
+            self.counts.writes.fetch_add(1, Ordering::SeqCst);
+            IoTarget::write_at(&self.file, content, offset).await
+        }
+
+        async fn sync(&self, mode: SyncMode) -> Result<()> {
+            match mode {
+                SyncMode::Data => self.counts.data_syncs.fetch_add(1, Ordering::SeqCst),
+                SyncMode::All => self.counts.full_syncs.fetch_add(1, Ordering::SeqCst),
+            };
+            IoTarget::sync(&self.file, mode).await
         }
     }
 
@@ -26,7 +100,7 @@ mod tests {
         let registry = Arc::new(Registry::new());
         
         let file = create_test_file(&path);
-        registry.insert(1, file, Duration::from_millis(1000), Duration::from_millis(1000));
+        registry.insert(1, file, Duration::from_millis(1000), Duration::from_millis(1000)).unwrap();
 
         let writer = registry.get_writer::<std::fs::File>(1).unwrap();
         let reader = registry.get_reader::<std::fs::File>(1).unwrap();
@@ -52,7 +126,7 @@ mod tests {
         let registry = Arc::new(Registry::new());
 
         let file = create_test_file(&path);
-        registry.insert(12345, file, Duration::from_millis(1000), Duration::from_millis(1000));
+        registry.insert(12345, file, Duration::from_millis(1000), Duration::from_millis(1000)).unwrap();
 
         let num_tasks = 20;
         let ops_per_task = 20;
@@ -86,4 +160,1408 @@ mod tests {
         drop(registry);
         let _ = std::fs::remove_file(&path);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_wal_recovers_writes_after_crash_mid_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data.dat");
+        let wal_path = dir.path().join("data.wal");
+
+        {
+            let registry = Registry::new();
+            let target = StallingTarget::new(create_test_file(data_path.to_str().unwrap()), 1);
+            registry.insert_with(7, target, IoConfig::builder().wal(&wal_path).build()).unwrap();
+
+            let writer = registry.get_writer::<StallingTarget>(7).unwrap();
+            for i in 0..4u8 {
+                writer.write_at(i as u64 * 1024, vec![b'a' + i; 100]).await.unwrap();
+            }
+
+            let flusher = registry.get_writer::<StallingTarget>(7).unwrap();
+            let flush = tokio::spawn(async move { flusher.flush().await });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flush.abort();
+            let _ = flush.await;
+
+            // The process "dies" here: nothing gets a chance to flush on drop.
+            std::mem::forget(writer);
+        }
+
+        let on_disk = std::fs::read(&data_path).unwrap();
+        assert!(on_disk.len() < 3 * 1024, "flush was expected to stop partway");
+
+        let registry = Registry::new();
+        let file = std::fs::OpenOptions::new().read(true).write(true).open(&data_path).unwrap();
+        registry.insert_with(7, file, IoConfig::builder().wal(&wal_path).build()).unwrap();
+
+        let reader = registry.get_reader::<std::fs::File>(7).unwrap();
+        for i in 0..4u8 {
+            let data = reader.read_at(i as u64 * 1024, 100).await.unwrap();
+            assert_eq!(data.as_ref(), vec![b'a' + i; 100].as_slice());
+        }
+
+        let writer = registry.get_writer::<std::fs::File>(7).unwrap();
+        writer.flush().await.unwrap();
+
+        let on_disk = std::fs::read(&data_path).unwrap();
+        for i in 0..4usize {
+            assert_eq!(&on_disk[i * 1024..i * 1024 + 100], vec![b'a' + i as u8; 100].as_slice());
+        }
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wal_ignores_torn_tail_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data.dat");
+        let wal_path = dir.path().join("data.wal");
+
+        {
+            let registry = Registry::new();
+            let file = create_test_file(data_path.to_str().unwrap());
+            registry.insert_with(3, file, IoConfig::builder().wal(&wal_path).build()).unwrap();
+
+            let writer = registry.get_writer::<std::fs::File>(3).unwrap();
+            writer.write_at(0, Bytes::from("header")).await.unwrap();
+            writer.write_at(100, Bytes::from("body")).await.unwrap();
+            std::mem::forget(writer);
+        }
+
+        let intact_len = std::fs::metadata(&wal_path).unwrap().len();
+        {
+            // A length prefix promising 64 bytes, followed by a truncated crc and payload.
+            let mut wal = std::fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+            wal.write_all(&64u32.to_le_bytes()).unwrap();
+            wal.write_all(&[0xAB; 9]).unwrap();
+        }
+
+        let registry = Registry::new();
+        let file = std::fs::OpenOptions::new().read(true).write(true).open(&data_path).unwrap();
+        registry.insert_with(3, file, IoConfig::builder().wal(&wal_path).build()).unwrap();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), intact_len);
+
+        let reader = registry.get_reader::<std::fs::File>(3).unwrap();
+        assert_eq!(reader.read_at(0, 6).await.unwrap(), Bytes::from("header"));
+        assert_eq!(reader.read_at(100, 4).await.unwrap(), Bytes::from("body"));
+    }
+
+    #[tokio::test]
+    async fn test_durability_policies() {
+        // (policy, (data, full) syncs after auto flushes, after flush, after shutdown)
+        let cases = [
+            (Durability::None, (0, 0), (0, 0), (0, 0)),
+            (Durability::FlushOnly, (0, 0), (1, 0), (2, 0)),
+            (Durability::DataSync, (3, 0), (4, 0), (5, 0)),
+            (Durability::FullSync, (0, 3), (0, 4), (0, 5)),
+            (Durability::SyncEveryN(2), (1, 0), (2, 0), (3, 0)),
+        ];
+
+        for (i, (policy, after_auto, after_flush, after_shutdown)) in cases.into_iter().enumerate() {
+            let path = format!("test_durability_{}_{}.dat", line!(), i);
+            let counts = Arc::new(Counts::default());
+            let registry = Registry::new();
+            let target = CountingTarget { file: create_test_file(&path), counts: counts.clone() };
+            registry.insert(1, target, Duration::from_millis(1000), Duration::from_millis(1000)).unwrap();
+
+            let writer = registry.get_writer::<CountingTarget>(1).unwrap();
+            writer.set_durability(policy);
+            assert_eq!(writer.durability(), policy);
+
+            // Three automatic flushes, each triggered by the queue crossing 16 KiB.
+            for round in 0..3u64 {
+                for j in 0..5u64 {
+                    writer.write_at(round * 65536 + j * 4000, vec![7u8; 4000]).await.unwrap();
+                }
+            }
+            assert_eq!(counts.syncs(), after_auto, "{:?} after auto flushes", policy);
+
+            writer.write_at(200_000, Bytes::from("tail")).await.unwrap();
+            writer.flush().await.unwrap();
+            assert_eq!(counts.syncs(), after_flush, "{:?} after flush", policy);
+
+            writer.write_at(300_000, Bytes::from("last")).await.unwrap();
+            writer.shutdown().await.unwrap();
+            assert_eq!(counts.syncs(), after_shutdown, "{:?} after shutdown", policy);
+
+            drop(writer);
+            drop(registry);
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_target_config() {
+        let path = format!("test_config_{}.dat", line!());
+        let counts = Arc::new(Counts::default());
+        let registry = Registry::new();
+        let target = CountingTarget { file: create_test_file(&path), counts: counts.clone() };
+        let config = IoConfig::builder()
+            .small_write_cutoff(64)
+            .latency_threshold_ns(u64::MAX)
+            .max_queue_ops(3)
+            .max_queue_bytes(1024 * 1024)
+            .durability(Durability::DataSync)
+            .build();
+        registry.insert_with(1, target, config).unwrap();
+
+        let writer = registry.get_writer::<CountingTarget>(1).unwrap();
+        let handle = registry.config::<CountingTarget>(1).unwrap();
+        assert_eq!(handle.get().max_queue_ops(), 3);
+        assert_eq!(writer.durability(), Durability::DataSync);
+
+        // Above the cutoff: straight to the target.
+        writer.write_at(0, vec![1u8; 100]).await.unwrap();
+        assert_eq!(counts.writes(), 1);
+
+        // Below the cutoff: queued until the op limit is reached.
+        writer.write_at(1000, vec![2u8; 10]).await.unwrap();
+        writer.write_at(2000, vec![3u8; 10]).await.unwrap();
+        assert_eq!(counts.writes(), 1);
+        writer.write_at(3000, vec![4u8; 10]).await.unwrap();
+        assert_eq!(counts.writes(), 4);
+
+        // Runtime changes apply to the next write.
+        handle.set_small_write_cutoff(4);
+        writer.write_at(4000, vec![5u8; 10]).await.unwrap();
+        assert_eq!(counts.writes(), 5);
+        assert_eq!(writer.config().get().small_write_cutoff(), 4);
+
+        drop(writer);
+        drop(registry);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_memory_target_sparse_semantics() {
+        let target = MemoryTarget::with_content("abc");
+
+        target.write_at(Bytes::from("xy"), 6).await.unwrap();
+        assert_eq!(target.snapshot(), Bytes::from_static(b"abc\0\0\0xy"));
+
+        target.write_at(Bytes::from("Z"), 1).await.unwrap();
+        assert_eq!(target.snapshot(), Bytes::from_static(b"aZc\0\0\0xy"));
+
+        let tail = target.read_at(6, 4).await.unwrap();
+        assert_eq!(tail, Bytes::from_static(b"xy\0\0"));
+        assert_eq!(target.read_at(100, 3).await.unwrap(), Bytes::from_static(b"\0\0\0"));
+        assert_eq!(target.size(), 8);
+        assert_eq!(target.len().await.unwrap(), 8);
+
+        assert!(matches!(target.read_at(u64::MAX, 2).await, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput));
+        assert!(matches!(target.write_at(Bytes::from("ab"), u64::MAX).await, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput));
+        assert_eq!(target.size(), 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_memory_target_through_buffers() {
+        let registry = Arc::new(Registry::new());
+        registry.insert(9, MemoryTarget::new(), Duration::from_millis(1000), Duration::from_millis(1000)).unwrap();
+
+        let mut handles = vec![];
+        for i in 0..8u64 {
+            let reg = registry.clone();
+            handles.push(tokio::spawn(async move {
+                let writer = reg.get_writer::<MemoryTarget>(9).unwrap();
+                let reader = reg.get_reader::<MemoryTarget>(9).unwrap();
+                for j in 0..16u64 {
+                    let offset = (i * 16 + j) * 8;
+                    let data = format!("m{:02}n{:02}zz", i, j).into_bytes();
+                    writer.write_at(offset, data.clone()).await.unwrap();
+                    assert_eq!(reader.read_at(offset, 8).await.unwrap().as_ref(), data.as_slice());
+                }
+            }));
+        }
+        for h in handles { h.await.unwrap(); }
+
+        let writer = registry.get_writer::<MemoryTarget>(9).unwrap();
+        writer.flush().await.unwrap();
+
+        let reader = registry.get_reader::<MemoryTarget>(9).unwrap();
+        let all = reader.read_at(0, 8 * 16 * 8).await.unwrap();
+        for i in 0..8u64 {
+            for j in 0..16u64 {
+                let offset = ((i * 16 + j) * 8) as usize;
+                assert_eq!(&all[offset..offset + 8], format!("m{:02}n{:02}zz", i, j).as_bytes());
+            }
+        }
+    }
+
+    type Faulty = FaultyTarget<Arc<MemoryTarget>>;
+
+    fn faulty_registry(plan: FaultPlan, config: IoConfig) -> (Registry, Arc<MemoryTarget>) {
+        let memory = Arc::new(MemoryTarget::new());
+        let registry = Registry::new();
+        registry.insert_with(1, FaultyTarget::new(memory.clone(), plan), config).unwrap();
+        (registry, memory)
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_queued_writes() {
+        let plan = FaultPlan::new().on_write(2, Fault::Fail);
+        let (registry, memory) = faulty_registry(plan, IoConfig::default());
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+        let reader = registry.get_reader::<Faulty>(1).unwrap();
+
+        for i in 0..4u8 {
+            writer.write_at(i as u64 * 100, vec![b'a' + i; 10]).await.unwrap();
+        }
+
+        assert!(matches!(writer.flush().await, Err(Error::Io(_))));
+        assert_eq!(&memory.snapshot()[..10], vec![b'a'; 10].as_slice());
+
+        for i in 0..4u8 {
+            let data = reader.read_at(i as u64 * 100, 10).await.unwrap();
+            assert_eq!(data.as_ref(), vec![b'a' + i; 10].as_slice());
+        }
+
+        // A newer write to a range that is waiting for a retry must still win.
+        writer.write_at(300, Bytes::from("newer")).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let snapshot = memory.snapshot();
+        for i in 0..3usize {
+            assert_eq!(&snapshot[i * 100..i * 100 + 10], vec![b'a' + i as u8; 10].as_slice());
+        }
+        assert_eq!(&snapshot[300..310], b"newerddddd");
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_drops_landed_runs_from_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal_path = dir.path().join("data.wal");
+        let plan = FaultPlan::new().on_write(2, Fault::Fail);
+        let (registry, memory) = faulty_registry(plan, IoConfig::builder().wal(&wal_path).build());
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+
+        writer.write_at(0, vec![b'a'; 10]).await.unwrap();
+        writer.write_at(10_000, vec![b'b'; 10]).await.unwrap();
+        assert!(writer.flush().await.is_err());
+
+        // The first run landed, so this write goes straight to the target.
+        writer.write_at(0, vec![b'z'; 8 * 1024]).await.unwrap();
+        std::mem::forget(writer);
+
+        let registry = Registry::new();
+        registry.insert_with(1, memory.clone(), IoConfig::builder().wal(&wal_path).build()).unwrap();
+        let writer = registry.get_writer::<Arc<MemoryTarget>>(1).unwrap();
+        writer.flush().await.unwrap();
+
+        let snapshot = memory.snapshot();
+        assert_eq!(&snapshot[..10], vec![b'z'; 10].as_slice());
+        assert_eq!(&snapshot[10_000..10_010], vec![b'b'; 10].as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_torn_write_during_flush_is_retried() {
+        let plan = FaultPlan::new().on_write(1, Fault::Truncate(3));
+        let (registry, memory) = faulty_registry(plan, IoConfig::default());
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+
+        writer.write_at(0, Bytes::from("abcdef")).await.unwrap();
+        assert!(writer.flush().await.is_err());
+        assert_eq!(memory.snapshot(), Bytes::from_static(b"abc"));
+
+        writer.flush().await.unwrap();
+        assert_eq!(memory.snapshot(), Bytes::from_static(b"abcdef"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_target_times_out_without_losing_writes() {
+        let plan = FaultPlan::new().on_write(1, Fault::Delay(Duration::from_millis(200)));
+        let config = IoConfig::builder().write_timeout(Duration::from_millis(20)).build();
+        let (registry, memory) = faulty_registry(plan, config);
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+
+        writer.write_at(0, Bytes::from("slow")).await.unwrap();
+        assert!(matches!(writer.flush().await, Err(Error::Timeout)));
+
+        writer.flush().await.unwrap();
+        assert_eq!(memory.snapshot(), Bytes::from_static(b"slow"));
+    }
+
+    #[tokio::test]
+    async fn test_injected_timeout_and_read_faults() {
+        let plan = FaultPlan::new()
+            .every_write(Fault::Timeout)
+            .on_read(1, Fault::Truncate(2))
+            .on_read(2, Fault::Corrupt)
+            .on_read(3, Fault::Fail);
+        let config = IoConfig::builder().small_write_cutoff(0).build();
+        let (registry, memory) = faulty_registry(plan, config);
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+        let reader = registry.get_reader::<Faulty>(1).unwrap();
+
+        // Direct writes surface the timeout to the caller.
+        assert!(matches!(writer.write_at(0, Bytes::from("data")).await, Err(Error::Timeout)));
+        memory.write_at(Bytes::from("data"), 0).await.unwrap();
+
+        // A short read is padded with zeros instead of panicking.
+        assert_eq!(reader.read_at(0, 4).await.unwrap(), Bytes::from_static(b"da\0\0"));
+        assert_eq!(reader.read_at(0, 1).await.unwrap(), Bytes::from(vec![!b'd']));
+        assert!(matches!(reader.read_at(0, 4).await, Err(Error::Io(_))));
+        assert_eq!(reader.read_at(0, 4).await.unwrap(), Bytes::from("data"));
+    }
+
+    #[test]
+    fn test_retry_backoff_grows_and_caps() {
+        let policy = RetryPolicy::new(5)
+            .base_delay(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(35))
+            .jitter(false);
+        assert_eq!(policy.delay_for(1), Duration::from_millis(10));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(20));
+        assert_eq!(policy.delay_for(3), Duration::from_millis(35));
+
+        let jittered = policy.jitter(true);
+        for attempt in 1..5 {
+            let delay = jittered.delay_for(attempt);
+            let cap = Duration::from_millis(10 << (attempt - 1)).min(Duration::from_millis(35));
+            assert!(delay >= cap / 2 && delay <= cap);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_retries_transient_errors() {
+        let memory = Arc::new(MemoryTarget::new());
+        let plan = FaultPlan::new().on_write(1, Fault::Fail).on_write(2, Fault::Timeout);
+        let faulty = Arc::new(FaultyTarget::new(memory.clone(), plan));
+        let config = IoConfig::builder()
+            .retry(RetryPolicy::new(3).base_delay(Duration::from_millis(1)))
+            .build();
+
+        let registry = Registry::new();
+        registry.insert_with(1, faulty.clone(), config).unwrap();
+        let writer = registry.get_writer::<Arc<Faulty>>(1).unwrap();
+
+        writer.write_at(0, Bytes::from("retried")).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(faulty.writes(), 3);
+        assert_eq!(memory.snapshot(), Bytes::from("retried"));
+
+        // Errors the classifier rejects are not retried.
+        faulty.set_plan(FaultPlan::new().every_write(Fault::Timeout));
+        writer.config().set_retry(
+            RetryPolicy::new(3).base_delay(Duration::from_millis(1)).classifier(|e| matches!(e, Error::Io(_)))
+        );
+        writer.write_at(100, Bytes::from("again")).await.unwrap();
+        assert!(matches!(writer.flush().await, Err(Error::Timeout)));
+        assert_eq!(faulty.writes(), 4);
+
+        let reader = registry.get_reader::<Arc<Faulty>>(1).unwrap();
+        assert_eq!(reader.read_at(100, 5).await.unwrap(), Bytes::from("again"));
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_go_to_dead_letter() {
+        let letters = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = letters.clone();
+        let config = IoConfig::builder()
+            .retry(RetryPolicy::new(2).base_delay(Duration::from_millis(1)))
+            .dead_letter(DeadLetterHandler::new(move |letter| sink.lock().unwrap().push(letter)))
+            .build();
+        // Both runs are tried before the retry, so the retry of the first one is write 3.
+        let plan = FaultPlan::new().on_write(1, Fault::Fail).on_write(3, Fault::Fail);
+        let (registry, memory) = faulty_registry(plan, config);
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+
+        writer.write_at(0, Bytes::from("lost")).await.unwrap();
+        writer.write_at(10, Bytes::from("kept")).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let letters = letters.lock().unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].offset, 0);
+        assert_eq!(letters[0].data, Bytes::from("lost"));
+        assert!(matches!(letters[0].error, Error::Io(_)));
+        assert_eq!(&memory.snapshot()[10..], b"kept");
+    }
+
+    #[tokio::test]
+    async fn test_target_budget_fail_fast() {
+        let config = IoConfig::builder()
+            .memory_budget(100)
+            .backpressure(BackpressureMode::FailFast)
+            .build();
+        let registry = Registry::new();
+        registry.insert_with(1, MemoryTarget::new(), config).unwrap();
+        let writer = registry.get_writer::<MemoryTarget>(1).unwrap();
+        let metrics = writer.metrics();
+
+        writer.write_at(0, vec![1u8; 60]).await.unwrap();
+        assert_eq!(metrics.queued_bytes.load(Ordering::SeqCst), 60);
+        assert!(matches!(writer.write_at(60, vec![2u8; 60]).await, Err(Error::QueueFull)));
+        assert_eq!(metrics.queued_bytes.load(Ordering::SeqCst), 60);
+
+        writer.flush().await.unwrap();
+        assert_eq!(metrics.queued_bytes.load(Ordering::SeqCst), 0);
+        writer.write_at(60, vec![2u8; 60]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_target_budget_waits_for_flush() {
+        let plan = FaultPlan::new().every_write(Fault::Delay(Duration::from_millis(10)));
+        let config = IoConfig::builder().memory_budget(100).build();
+        let (registry, memory) = faulty_registry(plan, config);
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+        let metrics = writer.metrics();
+
+        for i in 0..10u64 {
+            writer.write_at(i * 60, vec![i as u8; 60]).await.unwrap();
+            assert!(metrics.queued_bytes.load(Ordering::SeqCst) <= 100);
+        }
+        writer.flush().await.unwrap();
+
+        let snapshot = memory.snapshot();
+        for i in 0..10usize {
+            assert_eq!(&snapshot[i * 60..i * 60 + 60], vec![i as u8; 60].as_slice());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_budget_is_shared() {
+        let registry = Arc::new(Registry::with_memory_budget(100));
+        registry.insert_with(1, MemoryTarget::new(), IoConfig::default()).unwrap();
+        let config = IoConfig::builder().backpressure(BackpressureMode::FailFast).build();
+        registry.insert_with(2, MemoryTarget::new(), config).unwrap();
+
+        let first = registry.get_writer::<MemoryTarget>(1).unwrap();
+        let second = registry.get_writer::<MemoryTarget>(2).unwrap();
+
+        first.write_at(0, vec![1u8; 80]).await.unwrap();
+        assert_eq!(registry.memory_budget().unwrap().used(), 80);
+        assert!(matches!(second.write_at(0, vec![2u8; 50]).await, Err(Error::QueueFull)));
+
+        // In wait mode the second target blocks until the first one releases its bytes.
+        second.config().set_backpressure(BackpressureMode::Wait);
+        let flusher = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            first.flush().await.unwrap();
+        });
+        second.write_at(0, vec![2u8; 50]).await.unwrap();
+        flusher.await.unwrap();
+        assert_eq!(registry.memory_budget().unwrap().used(), 50);
+    }
+
+    /// Applies writes the slow, obvious way: one after another, growing like a sparse file.
+    fn apply_to_model(model: &mut Vec<u8>, offset: usize, data: &[u8]) {
+        if model.len() < offset + data.len() {
+            model.resize(offset + data.len(), 0);
+        }
+        model[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    #[tokio::test]
+    async fn test_flush_matches_sequential_model() {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+
+        for seed in 0..64u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let initial: Vec<u8> = (0..rng.gen_range(0..256)).map(|_| rng.r#gen()).collect();
+            let config = IoConfig::builder()
+                .small_write_cutoff(usize::MAX)
+                .max_queue_bytes(u64::MAX)
+                .coalesce_gap(rng.gen_range(0..16))
+                .build();
+
+            let memory = Arc::new(MemoryTarget::with_content(&initial));
+            let registry = Registry::new();
+            registry.insert_with(1, memory.clone(), config).unwrap();
+            let writer = registry.get_writer::<Arc<MemoryTarget>>(1).unwrap();
+            let reader = registry.get_reader::<Arc<MemoryTarget>>(1).unwrap();
+
+            let mut model = initial.clone();
+            for _ in 0..rng.gen_range(1..48) {
+                let offset = rng.gen_range(0..256usize);
+                let data: Vec<u8> = (0..rng.gen_range(1..32)).map(|_| rng.r#gen()).collect();
+                writer.write_at(offset as u64, data.clone()).await.unwrap();
+                apply_to_model(&mut model, offset, &data);
+
+                if rng.gen_bool(0.1) {
+                    writer.flush().await.unwrap();
+                }
+                if rng.gen_bool(0.2) {
+                    let (offset, len) = (rng.gen_range(0..288usize), rng.gen_range(1..32usize));
+                    let mut expected = model.get(offset..).unwrap_or_default().to_vec();
+                    expected.resize(len, 0);
+                    let got = reader.read_at(offset as u64, len as u64).await.unwrap();
+                    assert_eq!(got.as_ref(), &expected[..len], "seed {} read at {}", seed, offset);
+                }
+            }
+
+            writer.flush().await.unwrap();
+            assert_eq!(memory.snapshot().as_ref(), model.as_slice(), "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_fills_small_gaps() {
+        let memory = Arc::new(MemoryTarget::with_content("0123456789abcdef"));
+        let target = Arc::new(FaultyTarget::new(memory.clone(), FaultPlan::new()));
+        let registry = Registry::new();
+        registry.insert_with(1, target.clone(), IoConfig::builder().coalesce_gap(4).build()).unwrap();
+        let writer = registry.get_writer::<Arc<Faulty>>(1).unwrap();
+
+        writer.write_at(0, Bytes::from("AA")).await.unwrap();
+        writer.write_at(1, Bytes::from("BB")).await.unwrap();
+        writer.write_at(6, Bytes::from("CC")).await.unwrap();
+        writer.write_at(14, Bytes::from("DD")).await.unwrap();
+        writer.flush().await.unwrap();
+
+        // The first three writes go out as one run, the last one is too far away.
+        assert_eq!(memory.snapshot(), Bytes::from_static(b"ABB345CC89abcdDD"));
+        assert_eq!(target.writes(), 2);
+        assert_eq!(target.reads(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_direct_write_over_queued_data_wins() {
+        let config = IoConfig::builder().small_write_cutoff(8).build();
+        let registry = Registry::new();
+        let memory = Arc::new(MemoryTarget::new());
+        registry.insert_with(1, memory.clone(), config).unwrap();
+        let writer = registry.get_writer::<Arc<MemoryTarget>>(1).unwrap();
+
+        writer.write_at(4, Bytes::from("old")).await.unwrap();
+        writer.write_at(0, Bytes::from("newer data")).await.unwrap();
+        writer.flush().await.unwrap();
+
+        assert_eq!(memory.snapshot(), Bytes::from_static(b"newer data"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_keeps_only_newest_bytes() {
+        let target = Arc::new(FaultyTarget::new(Arc::new(MemoryTarget::new()), FaultPlan::new()));
+        let registry = Registry::new();
+        registry.insert_with(1, target.clone(), IoConfig::builder().memory_budget(1024).build()).unwrap();
+        let writer = registry.get_writer::<Arc<Faulty>>(1).unwrap();
+        let reader = registry.get_reader::<Arc<Faulty>>(1).unwrap();
+        let metrics = writer.metrics();
+
+        writer.write_at(0, Bytes::from("aaaaaaaa")).await.unwrap();
+        writer.write_at(2, Bytes::from("bbbb")).await.unwrap();
+        writer.write_at(4, Bytes::from("cccccc")).await.unwrap();
+        assert_eq!(metrics.queued_bytes.load(Ordering::SeqCst), 10);
+
+        // Fully covered by queued writes, so the target is never asked.
+        assert_eq!(reader.read_at(0, 10).await.unwrap(), Bytes::from_static(b"aabbcccccc"));
+        assert_eq!(target.reads(), 0);
+        assert_eq!(reader.read_at(8, 4).await.unwrap(), Bytes::from_static(b"cc\0\0"));
+        assert_eq!(target.reads(), 1);
+
+        writer.flush().await.unwrap();
+        assert_eq!(target.inner().snapshot(), Bytes::from_static(b"aabbcccccc"));
+        assert_eq!(target.writes(), 1);
+        assert_eq!(metrics.queued_bytes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_block_cache_hits_and_invalidation() {
+        let memory = Arc::new(MemoryTarget::with_content("0123456789abcdefghijklmnopqrstuv"));
+        let target = Arc::new(FaultyTarget::new(memory.clone(), FaultPlan::new()));
+        let registry = Registry::builder().block_cache(8 * 3).block_size(8).build();
+        let config = IoConfig::builder().block_cache(true).small_write_cutoff(4).build();
+        registry.insert_with(1, target.clone(), config).unwrap();
+        let writer = registry.get_writer::<Arc<Faulty>>(1).unwrap();
+        let reader = registry.get_reader::<Arc<Faulty>>(1).unwrap();
+        let metrics = reader.metrics();
+
+        assert_eq!(reader.read_at(2, 4).await.unwrap(), Bytes::from_static(b"2345"));
+        assert_eq!(reader.read_at(4, 8).await.unwrap(), Bytes::from_static(b"456789ab"));
+        assert_eq!(target.reads(), 2);
+        assert_eq!(metrics.cache_hits.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.cache_misses.load(Ordering::Relaxed), 2);
+
+        // Direct and flushed writes both drop the blocks they touch.
+        writer.write_at(9, Bytes::from("XXXX")).await.unwrap();
+        writer.write_at(1, Bytes::from("Y")).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(reader.read_at(0, 16).await.unwrap(), Bytes::from_static(b"0Y2345678XXXXdef"));
+        assert_eq!(target.reads(), 3);
+
+        // Three blocks fit; reading a fourth evicts one.
+        reader.read_at(16, 16).await.unwrap();
+        assert_eq!(registry.block_cache().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_sequential_reads_are_prefetched() {
+        let content: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let target = Arc::new(FaultyTarget::new(Arc::new(MemoryTarget::with_content(&content)), FaultPlan::new()));
+        let registry = Registry::new();
+        registry.insert_with(1, target.clone(), IoConfig::builder().readahead(16 * 1024).build()).unwrap();
+        let writer = registry.get_writer::<Arc<Faulty>>(1).unwrap();
+        let reader = registry.get_reader::<Arc<Faulty>>(1).unwrap();
+        let metrics = reader.metrics();
+
+        let mut expected = content.clone();
+        for i in 0..32usize {
+            if i == 20 {
+                // Lands on data that has already been prefetched.
+                writer.write_at(21 * 1024, vec![7u8; 8 * 1024]).await.unwrap();
+                expected[21 * 1024..29 * 1024].fill(7);
+            }
+            let got = reader.read_at(i as u64 * 1024, 1024).await.unwrap();
+            assert_eq!(got.as_ref(), &expected[i * 1024..(i + 1) * 1024], "read {}", i);
+            tokio::task::yield_now().await;
+        }
+        assert!(metrics.readahead_hits.load(Ordering::Relaxed) >= 24);
+        assert!(target.reads() <= 10);
+
+        // Random access does not prefetch.
+        let reads = target.reads();
+        for offset in [50_000u64, 3_000, 40_000] {
+            reader.read_at(offset, 100).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(target.reads(), reads + 3);
+    }
+
+    #[tokio::test]
+    async fn test_vectored_io() {
+        let file = tempfile::tempfile().unwrap();
+        file.write_vectored_at(&[
+            (0, Bytes::from_static(b"abc")),
+            (3, Bytes::from_static(b"def")),
+            (10, Bytes::from_static(b"xyz")),
+        ]).await.unwrap();
+
+        let data = file.read_vectored_at(&[(0, 2), (2, 4), (8, 4), (12, 3)]).await.unwrap();
+        let data: Vec<&[u8]> = data.iter().map(|b| b.as_ref()).collect();
+        assert_eq!(data, [&b"ab"[..], b"cdef", b"\0\0xy", b"z\0\0"]);
+
+        // A flushed run goes out as one vectored write, its gaps filled by one vectored read.
+        let target = Arc::new(FaultyTarget::new(file, FaultPlan::new()));
+        let registry = Registry::new();
+        registry.insert_with(1, target.clone(), IoConfig::builder().coalesce_gap(16).build()).unwrap();
+        let writer = registry.get_writer::<Arc<FaultyTarget<std::fs::File>>>(1).unwrap();
+        let reader = registry.get_reader::<Arc<FaultyTarget<std::fs::File>>>(1).unwrap();
+
+        for offset in [20u64, 30, 40, 50] {
+            writer.write_at(offset, vec![b'q'; 4]).await.unwrap();
+        }
+        let data = reader.read_vectored_at(&[(0, 6), (20, 4), (22, 10)]).await.unwrap();
+        assert_eq!(data[0].as_ref(), b"abcdef");
+        assert_eq!(data[1].as_ref(), b"qqqq");
+        assert_eq!(data[2].as_ref(), b"qq\0\0\0\0\0\0qq");
+        assert_eq!(target.reads(), 1);
+
+        writer.flush().await.unwrap();
+        assert_eq!((target.writes(), target.reads()), (1, 2));
+        let data = reader.read_vectored_at(&[(10, 3), (26, 28)]).await.unwrap();
+        assert_eq!(data[0].as_ref(), b"xyz");
+        assert_eq!(data[1].as_ref(), b"\0\0\0\0qqqq\0\0\0\0\0\0qqqq\0\0\0\0\0\0qqqq");
+    }
+
+    #[tokio::test]
+    async fn test_truncate_orders_against_queued_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data.dat");
+        let wal_path = dir.path().join("data.wal");
+        let config = || IoConfig::builder().wal(&wal_path).max_queue_bytes(1 << 20).build();
+
+        {
+            let registry = Registry::new();
+            registry.insert_with(1, create_test_file(data_path.to_str().unwrap()), config()).unwrap();
+            let writer = registry.get_writer::<std::fs::File>(1).unwrap();
+            let reader = registry.get_reader::<std::fs::File>(1).unwrap();
+
+            writer.write_at(0, vec![b'a'; 10]).await.unwrap();
+            writer.write_at(20, vec![b'b'; 10]).await.unwrap();
+            assert_eq!(writer.len().await.unwrap(), 30);
+
+            writer.set_len(25).await.unwrap();
+            assert_eq!(writer.len().await.unwrap(), 25);
+            assert_eq!(reader.read_at(20, 10).await.unwrap().as_ref(), b"bbbbb\0\0\0\0\0");
+
+            writer.write_at(40, b"cc".to_vec()).await.unwrap();
+            assert_eq!(reader.len().await.unwrap(), 42);
+
+            // Crash before anything is flushed.
+            std::mem::forget(writer);
+        }
+
+        // The dropped tail must not come back from the log.
+        let registry = Registry::new();
+        let file = std::fs::OpenOptions::new().read(true).write(true).open(&data_path).unwrap();
+        registry.insert_with(1, file, config()).unwrap();
+        let writer = registry.get_writer::<std::fs::File>(1).unwrap();
+        writer.flush().await.unwrap();
+
+        let on_disk = std::fs::read(&data_path).unwrap();
+        let mut expected = vec![0u8; 42];
+        expected[..10].fill(b'a');
+        expected[20..25].fill(b'b');
+        expected[40..].fill(b'c');
+        assert_eq!(on_disk, expected);
+
+        writer.set_len(5).await.unwrap();
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"aaaaa");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_transaction_is_all_or_nothing() {
+        let registry = Arc::new(Registry::new());
+        registry.insert_with(1, MemoryTarget::new(), IoConfig::builder().max_queue_bytes(64).build()).unwrap();
+        let writer = registry.get_writer::<MemoryTarget>(1).unwrap();
+
+        let reader = registry.get_reader::<MemoryTarget>(1).unwrap();
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let watcher = {
+            let stop = stop.clone();
+            tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    let data = reader.read_at(0, 104).await.unwrap();
+                    assert_eq!(data[..4], data[100..], "header and body from different transactions");
+                }
+            })
+        };
+
+        for i in 0..200u8 {
+            let mut tx = writer.transaction().unwrap();
+            tx.write_at(0, vec![i; 4]).write_at(100, vec![i; 4]);
+            tx.commit().await.unwrap();
+        }
+        stop.store(true, Ordering::Relaxed);
+        watcher.await.unwrap();
+
+        let mut tx = writer.transaction().unwrap();
+        tx.write_at(0, b"lost".to_vec());
+        drop(tx);
+        let reader = registry.get_reader::<MemoryTarget>(1).unwrap();
+        assert_eq!(reader.read_at(0, 4).await.unwrap().as_ref(), &[199; 4]);
+    }
+
+    #[tokio::test]
+    async fn test_transaction_survives_a_crash_mid_commit() {
+        // Without a WAL, a durability policy cannot be kept by a transaction, so none starts.
+        let plan = FaultPlan::new().on_write(2, Fault::Fail);
+        let (registry, _) = faulty_registry(plan.clone(), IoConfig::builder().durability(Durability::DataSync).build());
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+        assert!(matches!(writer.transaction(), Err(Error::Target(TargetError::TransactionNeedsWal(1)))));
+        writer.set_durability(Durability::None);
+        let tx = writer.transaction().unwrap();
+        writer.set_durability(Durability::DataSync);
+        assert!(matches!(tx.commit().await, Err(Error::Target(TargetError::TransactionNeedsWal(1)))));
+
+        // With one, the flush dies after the first of the two runs has landed.
+        let dir = tempfile::tempdir().unwrap();
+        let wal_path = dir.path().join("data.wal");
+        let config = || IoConfig::builder().wal(&wal_path).durability(Durability::DataSync).build();
+        let (registry, memory) = faulty_registry(plan, config());
+        let writer = registry.get_writer::<Faulty>(1).unwrap();
+        let mut tx = writer.transaction().unwrap();
+        tx.write_at(0, b"head".to_vec()).write_at(100, b"body".to_vec());
+        tx.commit().await.unwrap();
+        assert!(writer.flush().await.is_err());
+        assert_eq!(&memory.snapshot()[..4], b"head");
+        std::mem::forget(writer);
+
+        let registry = Registry::new();
+        registry.insert_with(1, memory.clone(), config()).unwrap();
+        registry.get_writer::<Arc<MemoryTarget>>(1).unwrap().flush().await.unwrap();
+        let snapshot = memory.snapshot();
+        assert_eq!((&snapshot[..4], &snapshot[100..104]), (&b"head"[..], &b"body"[..]));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_recovers_whole_or_not_at_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data.dat");
+        let wal_path = dir.path().join("data.wal");
+        let file = create_test_file(data_path.to_str().unwrap());
+
+        {
+            let registry = Registry::new();
+            registry.insert_with(1, file, IoConfig::builder().wal(&wal_path).build()).unwrap();
+            let writer = registry.get_writer::<std::fs::File>(1).unwrap();
+            writer.write_at(0, b"plain".to_vec()).await.unwrap();
+            let mut tx = writer.transaction().unwrap();
+            tx.write_at(10, b"head".to_vec()).write_at(20, b"body".to_vec());
+            tx.commit().await.unwrap();
+            std::mem::forget(writer);
+        }
+
+        let recover = || {
+            let registry = Registry::new();
+            let file = std::fs::OpenOptions::new().read(true).write(true).open(&data_path).unwrap();
+            registry.insert_with(1, file, IoConfig::builder().wal(&wal_path).build()).unwrap();
+            registry.get_reader::<std::fs::File>(1).unwrap()
+        };
+
+        let reader = recover();
+        assert_eq!(reader.read_at(0, 24).await.unwrap().as_ref(), b"plain\0\0\0\0\0head\0\0\0\0\0\0body");
+        drop(reader);
+
+        // A torn transaction record loses the whole transaction.
+        let wal_len = std::fs::metadata(&wal_path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&wal_path).unwrap().set_len(wal_len - 3).unwrap();
+        let reader = recover();
+        assert_eq!(reader.read_at(0, 24).await.unwrap().as_ref(), b"plain\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0");
+    }
+
+    #[tokio::test]
+    async fn test_batch_recovers_across_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_path = dir.path().join("data.dat");
+        let index_path = dir.path().join("index.dat");
+        let journal_path = dir.path().join("batches.journal");
+        let open = |path: &std::path::Path| std::fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+
+        {
+            let registry = Registry::builder().journal(&journal_path).build();
+            registry.insert(1, create_test_file(data_path.to_str().unwrap()), Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+            let failing = FaultyTarget::new(create_test_file(index_path.to_str().unwrap()), FaultPlan::new().every_write(Fault::Fail));
+            registry.insert_with(2, failing, IoConfig::default()).unwrap();
+
+            let mut batch = registry.batch();
+            batch.write_at(1, 0, b"record".to_vec()).write_at(2, 0, b"idx".to_vec());
+            let mut unknown = registry.batch();
+            unknown.write_at(9, 0, b"x".to_vec());
+            assert!(unknown.commit().await.is_err());
+            assert!(batch.commit().await.is_err());
+            assert_eq!(std::fs::read(&data_path).unwrap(), b"record");
+            assert_eq!(std::fs::read(&index_path).unwrap(), b"");
+        }
+
+        let journal = std::fs::read(&journal_path).unwrap();
+        let recover = || async {
+            let registry = Registry::builder().journal(&journal_path).build();
+            registry.insert(1, open(&data_path), Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+            registry.insert(2, open(&index_path), Duration::from_secs(1), Duration::from_secs(1)).unwrap();
+            registry.recover_batches().await.unwrap();
+        };
+
+        recover().await;
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"record");
+        assert_eq!(std::fs::read(&index_path).unwrap(), b"idx");
+        assert_eq!(std::fs::metadata(&journal_path).unwrap().len(), 0);
+
+        // A torn journal record never reached the commit point, so neither file changes.
+        std::fs::write(&data_path, b"").unwrap();
+        std::fs::write(&index_path, b"").unwrap();
+        std::fs::write(&journal_path, &journal[..journal.len() - 2]).unwrap();
+        recover().await;
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"");
+        assert_eq!(std::fs::read(&index_path).unwrap(), b"");
+        assert_eq!(std::fs::metadata(&journal_path).unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_keeps_its_view_while_writes_go_on() {
+        let registry = Registry::new();
+        registry.insert_with(1, MemoryTarget::new(), IoConfig::builder().snapshot_limit(16).build()).unwrap();
+        let writer = registry.get_writer::<MemoryTarget>(1).unwrap();
+        let reader = registry.get_reader::<MemoryTarget>(1).unwrap();
+        let metrics = reader.metrics();
+
+        writer.write_at(0, b"aaaaaaaa".to_vec()).await.unwrap();
+        writer.flush().await.unwrap();
+        writer.write_at(10, b"bb".to_vec()).await.unwrap();
+
+        let snapshot = reader.snapshot().await;
+        assert_eq!(snapshot.size(), Some(12));
+        writer.write_at(2, b"cccc".to_vec()).await.unwrap();
+        writer.write_at(10, b"dd".to_vec()).await.unwrap();
+        writer.flush().await.unwrap();
+        writer.set_len(4).await.unwrap();
+        writer.write_at(20, b"ee".to_vec()).await.unwrap();
+        writer.flush().await.unwrap();
+
+        assert_eq!(reader.read_at(0, 12).await.unwrap().as_ref(), b"aacc\0\0\0\0\0\0\0\0");
+        assert_eq!(snapshot.read_at(0, 24).await.unwrap().as_ref(), b"aaaaaaaa\0\0bb\0\0\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(metrics.snapshot_bytes.load(Ordering::Relaxed), snapshot.memory_usage());
+        assert!(snapshot.memory_usage() > 0);
+        drop(snapshot);
+        assert_eq!(metrics.snapshot_bytes.load(Ordering::Relaxed), 0);
+
+        // Copying more than the limit expires the snapshot instead of holding writers back.
+        let snapshot = reader.snapshot().await;
+        writer.write_at(0, vec![b'f'; 24]).await.unwrap();
+        writer.flush().await.unwrap();
+        assert!(snapshot.is_expired());
+        assert!(matches!(snapshot.read_at(0, 4).await, Err(Error::SnapshotExpired)));
+        assert_eq!(metrics.snapshot_bytes.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.expired_snapshots.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_histogram_quantiles() {
+        let histogram = ringest_io::Histogram::new();
+        for nanos in 1..=1000 {
+            histogram.record(nanos * 1000);
+        }
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 1000);
+        assert_eq!(summary.max_ns, 1_000_000);
+        assert_eq!(summary.mean_ns, 500_500);
+        // Buckets are at most 12.5% wide.
+        assert!((500_000..=562_500).contains(&summary.p50_ns), "{}", summary.p50_ns);
+        assert!((990_000..=1_000_000).contains(&summary.p99_ns), "{}", summary.p99_ns);
+        assert!((999_000..=1_000_000).contains(&summary.p999_ns), "{}", summary.p999_ns);
+        assert_eq!(ringest_io::Histogram::new().quantile(0.5), 0);
+    }
+
+    #[tokio::test]
+    async fn test_registry_metrics_snapshot() {
+        let registry = Registry::new();
+        registry.insert_with(1, MemoryTarget::new(), IoConfig::builder().max_queue_bytes(1 << 20).build()).unwrap();
+        let slow = FaultyTarget::new(MemoryTarget::new(), FaultPlan::new().every_read(Fault::Delay(Duration::from_millis(50))));
+        registry.insert_with(2, slow, IoConfig::builder().read_timeout(Duration::from_millis(10)).build()).unwrap();
+
+        let writer = registry.get_writer::<MemoryTarget>(1).unwrap();
+        for i in 0..4u64 {
+            writer.write_at(i * 4, b"abcd".to_vec()).await.unwrap();
+        }
+        writer.write_at(100, b"tail".to_vec()).await.unwrap();
+        let report = &registry.metrics_snapshot()[&1];
+        assert_eq!((report.queued_writes, report.queued_bytes), (5, 20));
+
+        writer.flush().await.unwrap();
+        let reader = registry.get_reader::<MemoryTarget>(1).unwrap();
+        reader.read_at(0, 16).await.unwrap();
+        let slow = registry.get_reader::<FaultyTarget<MemoryTarget>>(2).unwrap();
+        assert!(matches!(slow.read_at(0, 4).await, Err(Error::Timeout)));
+
+        let snapshot = registry.metrics_snapshot();
+        let report = &snapshot[&1];
+        assert_eq!((report.writes, report.written_bytes, report.reads, report.read_bytes), (5, 20, 1, 16));
+        assert_eq!(report.total_ops, 6);
+        assert_eq!((report.flushes, report.flushed_bytes, report.flush_latency.count), (1, 20, 1));
+        assert_eq!(report.coalescing_ratio, 2.5);
+        assert_eq!(report.queued_writes, 0);
+        assert!(report.target_type.ends_with("MemoryTarget"));
+        assert_eq!((snapshot[&2].timeouts, snapshot[&2].read_bytes), (1, 0));
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["1"]["write_latency"]["count"], 5);
+        assert_eq!(json["2"]["reads"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_janitor_flushes_targets_of_every_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("janitor.dat");
+        let registry = Arc::new(Registry::new());
+        registry.insert_with(1, create_test_file(path.to_str().unwrap()), IoConfig::default()).unwrap();
+        registry.insert_with(2, MemoryTarget::new(), IoConfig::default()).unwrap();
+
+        // Held, so that dropping a writer does not flush in the janitor's place.
+        let file = registry.get_writer::<std::fs::File>(1).unwrap();
+        let memory = registry.get_writer::<MemoryTarget>(2).unwrap();
+        file.write_at(0, b"file".to_vec()).await.unwrap();
+        memory.write_at(0, b"memory".to_vec()).await.unwrap();
+        assert_eq!(registry.target(1).unwrap().queue_depth() + registry.target(2).unwrap().queue_depth(), 2);
+
+        let janitor = registry.start_janitor(0, Duration::from_millis(10));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while registry.metrics_snapshot().values().any(|report| report.flushes == 0) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"file");
+        let target = registry.target(2).unwrap();
+        assert!(target.target_type().ends_with("MemoryTarget"));
+        assert_eq!((target.queue_depth(), target.metrics().flushes.load(Ordering::Relaxed)), (0, 1));
+
+        // Once the queues are empty, direct writes give the janitor nothing to flush.
+        let started = janitor.flushes_started();
+        assert!(started >= 2);
+        memory.write_at(0, vec![1u8; 8 * 1024]).await.unwrap();
+        assert_eq!(target.queue_depth(), 0);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(janitor.flushes_started(), started);
+        janitor.stop().await.unwrap();
+        drop((file, memory));
+
+        // The janitor does not keep the registry alive.
+        let janitor = registry.start_janitor(0, Duration::from_millis(10));
+        drop(registry);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(janitor.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_every_target_and_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shutdown.dat");
+        let registry = Registry::new();
+        registry.insert_with(1, create_test_file(path.to_str().unwrap()), IoConfig::default()).unwrap();
+        registry.insert_with(2, MemoryTarget::new(), IoConfig::default()).unwrap();
+        let broken = FaultyTarget::new(MemoryTarget::new(), FaultPlan::new().every_write(Fault::Fail));
+        registry.insert_with(3, broken, IoConfig::default()).unwrap();
+
+        let file = registry.get_writer::<std::fs::File>(1).unwrap();
+        file.write_at(0, b"kept".to_vec()).await.unwrap();
+        registry.get_writer::<MemoryTarget>(2).unwrap().write_at(0, b"kept".to_vec()).await.unwrap();
+        registry.get_writer::<FaultyTarget<MemoryTarget>>(3).unwrap().write_at(0, b"lost".to_vec()).await.unwrap();
+
+        let report = registry.shutdown(Duration::from_secs(5)).await;
+        assert!(!report.is_clean());
+        assert_eq!(report.targets.len(), 3);
+        assert!(report.targets[&1].is_ok() && report.targets[&2].is_ok());
+        assert_eq!(report.failures().map(|(id, _)| id).collect::<Vec<_>>(), vec![3]);
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"kept");
+        assert!(matches!(file.write_at(4, b"late".to_vec()).await, Err(Error::Closed)));
+        let reader = registry.get_reader::<MemoryTarget>(2).unwrap();
+        assert_eq!(reader.read_at(0, 4).await.unwrap().as_ref(), b"kept");
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_syncs_under_default_policy() {
+        let path = format!("test_shutdown_sync_{}.dat", line!());
+        let counts = Arc::new(Counts::default());
+        let registry = Registry::new();
+        let target = CountingTarget { file: create_test_file(&path), counts: counts.clone() };
+        registry.insert_with(1, target, IoConfig::default()).unwrap();
+
+        let writer = registry.get_writer::<CountingTarget>(1).unwrap();
+        assert_eq!(writer.durability(), Durability::None);
+        writer.write_at(0, b"queued".to_vec()).await.unwrap();
+
+        assert!(registry.shutdown(Duration::from_secs(5)).await.is_clean());
+        assert_eq!(counts.writes(), 1);
+        assert_eq!(counts.syncs(), (1, 0));
+
+        drop(writer);
+        drop(registry);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_remove_flushes_before_unregistering() {
+        let memory = Arc::new(MemoryTarget::new());
+        let registry = Registry::new();
+        registry.insert_with(1, memory.clone(), IoConfig::default()).unwrap();
+        let writer = registry.get_writer::<Arc<MemoryTarget>>(1).unwrap();
+        writer.write_at(0, b"queued".to_vec()).await.unwrap();
+
+        registry.remove(1).await.unwrap();
+        assert!(matches!(registry.get_writer::<Arc<MemoryTarget>>(1), Err(Error::Target(TargetError::NotFound(1)))));
+        assert_eq!(memory.read_at(0, 6).await.unwrap().as_ref(), b"queued");
+        assert!(matches!(writer.write_at(0, b"late".to_vec()).await, Err(Error::Closed)));
+        assert!(registry.remove(1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_target_handles_reject_duplicates_and_stale_ids() {
+        let registry = Registry::new();
+        let first = registry.insert_with(1, MemoryTarget::new(), IoConfig::default()).unwrap();
+        assert!(matches!(
+            registry.insert_with(1, MemoryTarget::new(), IoConfig::default()),
+            Err(Error::Target(TargetError::AlreadyRegistered(1)))
+        ));
+        assert!(matches!(registry.get_writer::<MemoryTarget>(2), Err(Error::Target(TargetError::NotFound(2)))));
+        assert!(matches!(
+            registry.get_writer::<std::fs::File>(1),
+            Err(Error::Target(TargetError::WrongType { id: 1, .. }))
+        ));
+        registry.writer(&first).unwrap().write_at(0, b"old".to_vec()).await.unwrap();
+
+        registry.remove(1).await.unwrap();
+        let second = registry.insert_with(1, MemoryTarget::new(), IoConfig::default()).unwrap();
+        assert_ne!(first.generation(), second.generation());
+        assert!(matches!(registry.reader(&first), Err(Error::Target(TargetError::Stale(1)))));
+        registry.writer(&second).unwrap().write_at(0, b"new".to_vec()).await.unwrap();
+        assert_eq!(registry.reader(&second).unwrap().read_at(0, 3).await.unwrap().as_ref(), b"new");
+    }
+
+    #[cfg(feature = "metrics-export")]
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_openmetrics() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let registry = Arc::new(Registry::new());
+        registry.insert_with(7, MemoryTarget::new(), IoConfig::default()).unwrap();
+        let writer = registry.get_writer::<MemoryTarget>(7).unwrap();
+        writer.write_at(0, b"abcd".to_vec()).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(Arc::clone(&registry).serve_metrics(listener));
+
+        let get = |path: &'static str| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/openmetrics-text"));
+        assert!(response.contains("# TYPE ringest_writes counter\n"));
+        assert!(response.contains("ringest_writes_total{target_id=\"7\",target_type=\"ringest_io::memory::MemoryTarget\"} 1\n"));
+        assert!(response.contains("ringest_flush_latency_seconds_count{target_id=\"7\""));
+        assert!(response.ends_with("# EOF\n"));
+
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_spans_describe_each_request() {
+        use ringest_fs::{dir::Directory, filter::Filter};
+        use std::{collections::HashMap, sync::Mutex};
+        use tracing::{Subscriber, field::{Field, Visit}, span::{Attributes, Id, Record}};
+        use tracing_subscriber::{Layer, layer::{Context, SubscriberExt}, registry::LookupSpan};
+
+        #[derive(Default)]
+        struct Fields(HashMap<&'static str, String>);
+
+        impl Visit for Fields {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                self.0.insert(field.name(), value.to_string());
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                self.0.insert(field.name(), format!("{value:?}"));
+            }
+        }
+
+        type Closed = Vec<(&'static str, HashMap<&'static str, String>)>;
+
+        /// Every closed span, by name, with its fields.
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Closed>>);
+
+        impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+            fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+                let mut fields = Fields::default();
+                attrs.record(&mut fields);
+                ctx.span(id).unwrap().extensions_mut().insert(fields);
+            }
+
+            fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+                if let Some(fields) = ctx.span(id).unwrap().extensions_mut().get_mut::<Fields>() {
+                    values.record(fields);
+                }
+            }
+
+            fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+                let span = ctx.span(&id).unwrap();
+                let fields = span.extensions_mut().remove::<Fields>().unwrap_or_default();
+                self.0.lock().unwrap().push((span.name(), fields.0));
+            }
+        }
+
+        let spans = Spans::default();
+        let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(spans.clone()));
+        let find = |name: &str| spans.0.lock().unwrap().iter().rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, fields)| fields.clone())
+            .unwrap();
+
+        let registry = Registry::new();
+        registry.insert_with(3, MemoryTarget::new(), IoConfig::default()).unwrap();
+        let writer = registry.get_writer::<MemoryTarget>(3).unwrap();
+        let reader = registry.get_reader::<MemoryTarget>(3).unwrap();
+
+        writer.write_at(64, b"abcd".to_vec()).await.unwrap();
+        let write = find("write_at");
+        assert_eq!((write["target_id"].as_str(), write["offset"].as_str(), write["len"].as_str()), ("3", "64", "4"));
+        assert_eq!((write["path"].as_str(), write["queue_depth"].as_str(), write["outcome"].as_str()), ("queued", "1", "ok"));
+
+        reader.read_at(64, 4).await.unwrap();
+        assert_eq!(find("read_at")["source"], "queue");
+
+        writer.flush().await.unwrap();
+        let flush = find("flush");
+        assert_eq!((flush["reason"].as_str(), flush["len"].as_str(), flush["queue_depth"].as_str()), ("Flush", "4", "0"));
+        assert_eq!(flush["outcome"], "ok");
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        Directory::open(dir.path().to_path_buf(), Arc::new(Filter::builder().recursive(1).build())).await.unwrap();
+        let scan = find("scan");
+        assert_eq!((scan["depth"].as_str(), scan["dirs"].as_str(), scan["files"].as_str()), ("0", "1", "1"));
+        assert_eq!(scan["outcome"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_file_size_follows_writes() {
+        use ringest_fs::file::File;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        let path = path.to_str().unwrap();
+
+        let file = File::new(path, "a fairly long first line".to_string()).unwrap();
+        file.rewrite("short".to_string()).await.unwrap();
+        assert_eq!(file.size(), 5);
+        file.append(" and more".to_string()).await.unwrap();
+        assert_eq!(file.size(), 14);
+        assert_eq!(file.content().await.unwrap(), "short and more");
+
+        file.flush().await.unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "short and more");
+    }
+
+    #[tokio::test]
+    async fn test_cursors_stream_through_the_queue() {
+        use ringest_io::{ReadCursor, WriteCursor};
+        use std::io::SeekFrom;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+        let registry = Registry::new();
+        registry.insert_with(1, Arc::new(MemoryTarget::new()), IoConfig::builder().max_queue_bytes(1 << 20).build()).unwrap();
+
+        let mut writer = WriteCursor::new(registry.get_writer::<Arc<MemoryTarget>>(1).unwrap());
+        writer.write_all(b"hello, world").await.unwrap();
+        writer.seek(SeekFrom::Current(-5)).await.unwrap();
+        writer.write_all(b"there").await.unwrap();
+        assert_eq!(writer.position(), 12);
+        assert_eq!(writer.seek(SeekFrom::End(-1)).await.unwrap(), 11);
+
+        // Still queued, but visible to the reader.
+        let mut reader = ReadCursor::new(registry.get_reader::<Arc<MemoryTarget>>(1).unwrap());
+        let mut text = String::new();
+        reader.read_to_string(&mut text).await.unwrap();
+        assert_eq!(text, "hello, there");
+
+        reader.seek(SeekFrom::End(-5)).await.unwrap();
+        let mut tail = [0u8; 8];
+        let n = reader.read(&mut tail).await.unwrap();
+        assert_eq!(&tail[..n], b"there");
+        assert_eq!(reader.read(&mut tail).await.unwrap(), 0);
+
+        // Writes made after the reader was created move its end.
+        writer.seek(SeekFrom::End(0)).await.unwrap();
+        writer.write_all(b"!").await.unwrap();
+        assert_eq!(reader.read(&mut tail).await.unwrap(), 1);
+        assert_eq!(reader.seek(SeekFrom::End(-2)).await.unwrap(), 11);
+
+        writer.shutdown().await.unwrap();
+        assert_eq!(writer.into_inner().metrics().queued_bytes.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mmap_target_grows_and_reads_without_copy() {
+        use ringest_fs::file::{Backend, File};
+        use ringest_io::MmapTarget;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mmap.dat");
+        std::fs::write(&path, b"hello world").unwrap();
+
+        // Read-only mappings hand out slices of the mapping and take no writes.
+        let target = MmapTarget::read_only(std::fs::File::open(&path).unwrap()).unwrap();
+        let first = target.read_at(6, 5).await.unwrap();
+        let second = target.read_at(6, 5).await.unwrap();
+        assert_eq!(first.as_ref(), b"world");
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert!(target.write_at(Bytes::from_static(b"!"), 0).await.is_err());
+        assert!(target.set_len(0).await.is_err());
+        drop(target);
+
+        // Writable ones copy, so earlier reads never change under their owner.
+        let target = MmapTarget::open(&path).unwrap();
+        let first = target.read_at(6, 5).await.unwrap();
+        target.write_at(Bytes::from_static(b"WORLD"), 6).await.unwrap();
+        assert_eq!(first.as_ref(), b"world");
+        assert_eq!(target.read_at(6, 5).await.unwrap().as_ref(), b"WORLD");
+        target.write_at(Bytes::from_static(b"world"), 6).await.unwrap();
+
+        // Growing past the end remaps.
+        target.write_at(Bytes::from_static(b"!!"), 4095).await.unwrap();
+        assert_eq!(target.mapped_len(), 4097);
+        assert_eq!(target.read_at(4094, 5).await.unwrap().as_ref(), b"\0!!\0\0");
+        target.sync(SyncMode::Data).await.unwrap();
+        let on_disk = std::fs::read(&path).unwrap();
+        assert_eq!(on_disk.len(), 4097);
+        assert_eq!(&on_disk[4095..], b"!!");
+
+        // Growth from outside is picked up on the next read past the mapping.
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"tail").unwrap();
+        assert_eq!(target.read_at(4097, 4).await.unwrap().as_ref(), b"tail");
+        drop(target);
+
+        // Truncating works while the block cache holds data read from the mapping.
+        let registry = Registry::builder().block_cache(4096 * 4).build();
+        let config = IoConfig::builder().block_cache(true).build();
+        registry.insert_with(1, MmapTarget::open(&path).unwrap(), config).unwrap();
+        let reader = registry.get_reader::<MmapTarget>(1).unwrap();
+        let writer = registry.get_writer::<MmapTarget>(1).unwrap();
+        let held = reader.read_at(4000, 10).await.unwrap();
+        writer.set_len(100).await.unwrap();
+        assert_eq!(reader.len().await.unwrap(), 100);
+        assert_eq!(held.len(), 10);
+        writer.write_at(200, Bytes::from_static(b"!")).await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 201);
+        drop((reader, writer, registry));
+
+        // Files opened read-only through the mapping read without a copy.
+        let file = File::open_with(path.to_str().unwrap(), Backend::MmapReadOnly).unwrap();
+        let first = file.read_at(0, 5).await.unwrap();
+        assert_eq!(first.as_ref(), b"hello");
+        assert_eq!(first.as_ptr(), file.read_at(0, 5).await.unwrap().as_ptr());
+        file.write_at(0, "HELLO".to_string()).await.unwrap();
+        assert!(file.flush().await.is_err());
+        drop(file);
+
+        let file = File::open_with(path.to_str().unwrap(), Backend::Mmap).unwrap();
+        assert!(file.content().await.unwrap().starts_with("hello world"));
+        file.write_at(0, "HELLO".to_string()).await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(&std::fs::read(&path).unwrap()[..11], b"HELLO world");
+    }
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    #[tokio::test]
+    async fn test_uring_target_survives_rejected_requests() {
+        use ringest_io::UringTarget;
+
+        let dir = tempfile::tempdir().unwrap();
+        let target = UringTarget::open(dir.path().join("uring.dat")).unwrap();
+
+        let too_long = tokio::time::timeout(Duration::from_secs(5), target.read_at(0, u32::MAX as usize + 1)).await.unwrap();
+        assert!(matches!(too_long, Err(Error::Io(_))));
+
+        let write = target.write_at(Bytes::from("still served"), 0);
+        tokio::time::timeout(Duration::from_secs(5), write).await.unwrap().unwrap();
+        assert_eq!(target.read_at(0, 12).await.unwrap().as_ref(), b"still served");
+    }
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_uring_target_round_trip() {
+        use ringest_io::UringTarget;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("uring.dat");
+        let registry = Registry::new();
+        registry.insert_with(1, UringTarget::open(&path).unwrap(), IoConfig::builder().durability(Durability::DataSync).build()).unwrap();
+        let writer = registry.get_writer::<UringTarget>(1).unwrap();
+        let reader = registry.get_reader::<UringTarget>(1).unwrap();
+
+        // Queued writes flushed as one batch, a direct write larger than a registered buffer.
+        let big: Vec<u8> = (0..200 * 1024).map(|i| (i % 253) as u8).collect();
+        for i in 0..16u64 {
+            writer.write_at(i * 100, vec![b'a' + i as u8; 50]).await.unwrap();
+        }
+        writer.write_at(4096, big.clone()).await.unwrap();
+        writer.flush().await.unwrap();
+
+        let on_disk = std::fs::read(&path).unwrap();
+        for i in 0..16usize {
+            assert_eq!(&on_disk[i * 100..i * 100 + 50], vec![b'a' + i as u8; 50].as_slice());
+        }
+        assert_eq!(&on_disk[4096..], big.as_slice());
+
+        assert_eq!(reader.read_at(4096, big.len() as u64).await.unwrap().as_ref(), big.as_slice());
+        let tail = reader.read_at(on_disk.len() as u64 - 2, 4).await.unwrap();
+        assert_eq!(tail.as_ref(), &[big[big.len() - 2], big[big.len() - 1], 0, 0]);
+
+        // Adjacent pieces too large for a registered buffer go out as one writev.
+        let target = UringTarget::open(&path).unwrap();
+        let pieces: Vec<(u64, Bytes)> = (0..3u64).map(|i| (i * 40_000, Bytes::from(vec![b'0' + i as u8; 40_000]))).collect();
+        target.write_vectored_at(&pieces).await.unwrap();
+        let data = target.read_vectored_at(&[(39_999, 2), (79_999, 2)]).await.unwrap();
+        assert_eq!((data[0].as_ref(), data[1].as_ref()), (&b"01"[..], &b"12"[..]));
+    }
+}